@@ -0,0 +1,33 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lex::dump;
+use lex::scanner::{Associativity, Config};
+use lex::token::Token;
+
+// Every configuration must scan any input without panicking, make
+// progress on each token, keep spans ordered and in bounds, and account
+// for every byte of the input in token text or trivia.
+fuzz_target!(|data: (bool, bool, bool, &str)| {
+    let (char_literals, comments, operators, code) = data;
+    let mut config = Config { char_literals, comments, ..Default::default() };
+    if operators {
+        for spelling in ["|>", "<|>", "<=>", "=>", "..."] {
+            config.add_operator(spelling, 4, Associativity::Left).unwrap();
+        }
+    }
+    let records = dump::records(code, config);
+    let mut end = 0;
+    let mut text = String::with_capacity(code.len());
+    for record in &records {
+        assert!(record.location.begin() >= end);
+        assert!(record.location.end() as usize <= code.len());
+        assert!(record.token == Token::Eos || !record.location.is_empty());
+        end = record.location.end();
+        text.push_str(&record.trivia);
+        text.push_str(&record.text);
+    }
+    assert_eq!(records.last().map(|record| &record.token), Some(&Token::Eos));
+    assert_eq!(text, code);
+    assert_eq!(dump::from_binary(&dump::to_binary(&records)).unwrap(), records);
+});
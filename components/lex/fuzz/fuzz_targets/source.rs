@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lex::source::Source;
+use lex::token::Token;
+
+// Decoding arbitrary bytes either fails cleanly or yields text whose
+// token spans all map back inside the original input.
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(source) = Source::new(bytes) else {
+        return;
+    };
+    let mut s = source.scanner();
+    s.init();
+    let mut end = 0;
+    loop {
+        let token = s.next();
+        let span = source.original_span(s.location());
+        assert!(span.begin() >= end && span.end() >= span.begin());
+        assert!(span.end() as usize <= bytes.len());
+        end = span.end();
+        if token == Token::Eos {
+            break;
+        }
+    }
+});
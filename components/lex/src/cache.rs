@@ -0,0 +1,297 @@
+// On-disk cache of token streams, so unchanged files are not lexed again.
+//
+// Entries are keyed by a hash of the source text, the scanner Config and
+// LEXER_VERSION, and stored one file per key. A file holds the key, the
+// Config and version as hashed, the source itself, a table of the distinct
+// token payloads (names, literals), the number of tokens and per token the
+// kind, payload index and position. The key is a 64-bit FNV-1a hash, which
+// is easy to collide, so it only names the file: an entry is used only if
+// its Config and source are byte for byte the ones asked for. Token text
+// and trivia are sliced back out of the source.
+//
+// Anything unreadable, truncated or stale is treated as a miss.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use crate::dump::{self, BinaryReader, TokenRecord};
+use crate::scanner::{Associativity, Config, Span};
+
+// Bump whenever the scanner produces different tokens for some input.
+pub const LEXER_VERSION: u32 = 1;
+
+// Changes with the layout of the file.
+const MAGIC: &[u8; 4] = b"MTK3";
+
+pub struct TokenCache {
+    dir: PathBuf,
+}
+
+impl TokenCache {
+
+    pub fn new(dir: impl Into<PathBuf>) -> TokenCache {
+        TokenCache { dir: dir.into() }
+    }
+
+    // The cached tokens of `code`, lexing and storing them on a miss.
+    // Failing to write the cache is not an error for the caller.
+    pub fn records(&self, code: &str, config: &Config) -> Vec<TokenRecord> {
+        if let Some(records) = self.load(code, config) {
+            return records;
+        }
+        let records = dump::records(code, config.clone());
+        let _ = self.store(code, config, &records);
+        records
+    }
+
+    pub fn load(&self, code: &str, config: &Config) -> Option<Vec<TokenRecord>> {
+        let key = key(code, config);
+        let bytes = fs::read(self.path(key)).ok()?;
+        decode(&bytes, key, &settings(config), code)
+    }
+
+    pub fn store(&self, code: &str, config: &Config, records: &[TokenRecord]) -> io::Result<()> {
+        let key = key(code, config);
+        fs::create_dir_all(&self.dir)?;
+        // Write under a temporary name first so that a reader never sees a
+        // half-written entry.
+        let path = self.path(key);
+        let temp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, encode(records, key, &settings(config), code))?;
+        fs::rename(&temp, &path)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.mtok", key))
+    }
+
+}
+
+// FNV-1a over everything that can change the token stream.
+pub fn key(code: &str, config: &Config) -> u64 {
+    let mut hash = Fnv(0xcbf29ce484222325);
+    hash.write(&settings(config));
+    hash.write(code.as_bytes());
+    hash.0
+}
+
+// LEXER_VERSION and `config` as bytes, equal only for the same settings.
+fn settings(config: &Config) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&LEXER_VERSION.to_le_bytes());
+    out.extend_from_slice(&[config.char_literals as u8, config.comments as u8]);
+    out.extend_from_slice(&(config.operators.len() as u32).to_le_bytes());
+    for operator in &config.operators {
+        out.extend_from_slice(&(operator.spelling.len() as u32).to_le_bytes());
+        out.extend_from_slice(operator.spelling.as_bytes());
+        let associativity = match operator.associativity {
+            Associativity::Left => 0,
+            Associativity::Right => 1,
+            Associativity::None => 2,
+        };
+        out.extend_from_slice(&[operator.precedence, associativity]);
+    }
+    out
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+}
+
+fn encode(records: &[TokenRecord], key: u64, settings: &[u8], code: &str) -> Vec<u8> {
+    let mut strings: Vec<String> = Vec::new();
+    let mut interned: HashMap<String, u32> = HashMap::new();
+    let mut body = Vec::new();
+    for record in records {
+        let kind = dump::kind_byte(&record.token);
+        body.push(kind);
+        if let Some(value) = dump::payload(&record.token) {
+            let index = *interned.entry(value).or_insert_with_key(|value| {
+                strings.push(value.clone());
+                strings.len() as u32 - 1
+            });
+            dump::write_varint(&mut body, index);
+        }
+        dump::write_varint(&mut body, record.location.begin());
+        dump::write_varint(&mut body, record.location.len());
+        dump::write_varint(&mut body, record.line);
+        dump::write_varint(&mut body, record.column);
+    }
+    let mut out = Vec::with_capacity(body.len() + code.len() + 64);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&key.to_le_bytes());
+    dump::write_varint(&mut out, settings.len() as u32);
+    out.extend_from_slice(settings);
+    dump::write_binary_string(&mut out, code);
+    dump::write_varint(&mut out, strings.len() as u32);
+    for string in &strings {
+        dump::write_binary_string(&mut out, string);
+    }
+    dump::write_varint(&mut out, records.len() as u32);
+    out.extend_from_slice(&body);
+    out
+}
+
+fn decode(bytes: &[u8], key: u64, settings: &[u8], code: &str) -> Option<Vec<TokenRecord>> {
+    let mut reader = BinaryReader { input: bytes, position: 0 };
+    if reader.bytes(MAGIC.len()).ok()? != MAGIC {
+        return None;
+    }
+    let stored: [u8; 8] = reader.bytes(8).ok()?.try_into().ok()?;
+    if u64::from_le_bytes(stored) != key {
+        return None;
+    }
+    let length = reader.varint().ok()? as usize;
+    if reader.bytes(length).ok()? != settings {
+        return None;
+    }
+    let length = reader.varint().ok()? as usize;
+    if reader.bytes(length).ok()? != code.as_bytes() {
+        return None;
+    }
+    let count = reader.varint().ok()?;
+    let mut strings = Vec::new();
+    for _ in 0..count {
+        strings.push(reader.string().ok()?);
+    }
+    let count = reader.varint().ok()?;
+    let mut records = Vec::new();
+    let mut position = 0;
+    for _ in 0..count {
+        let kind = reader.byte().ok()?;
+        let value = match dump::has_payload(kind) {
+            true => Some(strings.get(reader.varint().ok()? as usize)?.as_str()),
+            false => None,
+        };
+        let token = dump::token_from_byte(kind, value).ok()?;
+        let begin = reader.varint().ok()?;
+        let end = begin.checked_add(reader.varint().ok()?)?;
+        let line = reader.varint().ok()?;
+        let column = reader.varint().ok()?;
+        records.push(TokenRecord {
+            token,
+            location: Span::new(begin, end),
+            line,
+            column,
+            text: code.get(begin as usize..end as usize)?.to_string(),
+            trivia: code.get(position..begin as usize)?.to_string(),
+        });
+        position = end as usize;
+    }
+    match reader.position == bytes.len() {
+        true => Some(records),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::token::Token;
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monty-token-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn stores_and_loads_records() {
+        let cache = TokenCache::new(temp_dir("round-trip"));
+        let code = "var s = 'hi'; // note\nif (s == 'hi') s = s + 'hi';\n";
+        let config = Config::default();
+        assert!(cache.load(code, &config).is_none());
+        let records = cache.records(code, &config);
+        assert_eq!(cache.load(code, &config), Some(records.clone()));
+        assert_eq!(records, dump::records(code, config.clone()));
+
+        // Other contents or another configuration miss.
+        assert!(cache.load("var s = 'ho';", &config).is_none());
+        let comments = Config { comments: true, ..Default::default() };
+        assert!(cache.load(code, &comments).is_none());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn ignores_corrupt_entries() {
+        let cache = TokenCache::new(temp_dir("corrupt"));
+        let code = "a + b";
+        let config = Config::default();
+        let records = cache.records(code, &config);
+        let path = cache.path(key(code, &config));
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        fs::write(&path, &bytes).unwrap();
+        assert!(cache.load(code, &config).is_none());
+        assert_eq!(cache.records(code, &config), records);
+        assert!(cache.load(code, &config).is_some());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn ignores_entries_cut_between_records() {
+        let cache = TokenCache::new(temp_dir("cut"));
+        let code = "a + b";
+        let config = Config::default();
+        let records = cache.records(code, &config);
+        assert_eq!(records.last().unwrap().token, Token::Eos);
+        // Drop the last record, the Eos, whole.
+        let (key, settings) = (key(code, &config), settings(&config));
+        let encoded = |records: &[TokenRecord], code: &str| encode(records, key, &settings, code);
+        let last = encoded(&records, code).len() - encoded(&records[..records.len() - 1], code).len();
+        let path = cache.path(key);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - last);
+        fs::write(&path, &bytes).unwrap();
+        assert!(cache.load(code, &config).is_none());
+        // Trailing bytes are not accepted either.
+        bytes = encoded(&records, code);
+        bytes.push(0);
+        fs::write(&path, &bytes).unwrap();
+        assert!(cache.load(code, &config).is_none());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn ignores_entries_for_other_sources_with_the_same_key() {
+        // As if "a - b" and "a + b" collided: the entry under the key of
+        // "a + b" holds the tokens of "a - b".
+        let cache = TokenCache::new(temp_dir("collision"));
+        let config = Config::default();
+        let (code, other) = ("a + b", "a - b");
+        let records = cache.records(code, &config);
+        let path = cache.path(key(code, &config));
+        let other_records = dump::records(other, config.clone());
+        fs::write(&path, encode(&other_records, key(code, &config), &settings(&config), other)).unwrap();
+        assert!(cache.load(code, &config).is_none());
+        // Likewise for another Config.
+        let comments = Config { comments: true, ..Default::default() };
+        fs::write(&path, encode(&records, key(code, &config), &settings(&comments), code)).unwrap();
+        assert!(cache.load(code, &config).is_none());
+        fs::write(&path, encode(&records, key(code, &config), &settings(&config), code)).unwrap();
+        assert_eq!(cache.load(code, &config), Some(records));
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn interns_repeated_payloads() {
+        let code = "x x x x x x x x";
+        let records = dump::records(code, Config::default());
+        let encoded = encode(&records, 0, &[], "");
+        assert_eq!(encoded.iter().filter(|&&byte| byte == b'x').count(), 1);
+    }
+}
@@ -0,0 +1,366 @@
+use crate::scanner::{ScanError, Span};
+use crate::source::SourceMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        }
+    }
+
+    // SGR parameters for the severity's color.
+    fn style(self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+            Severity::Note => "1;32",
+            Severity::Help => "1;36",
+        }
+    }
+
+}
+
+// A span pointed at in the excerpt. The primary label marks where the
+// problem is, secondary ones what is related to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+// Whether a tool may apply a suggestion without asking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+}
+
+// Replaces `span` with `replacement`; an empty span inserts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<Edit>,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+
+    // A suggestion to insert `text` at `position`.
+    pub fn insert(message: impl Into<String>, position: u32, text: impl Into<String>, applicability: Applicability) -> Suggestion {
+        Suggestion {
+            message: message.into(),
+            edits: vec![Edit { span: Span::new(position, position), replacement: text.into() }],
+            applicability,
+        }
+    }
+
+    // `code` with the edits made. They must not overlap.
+    pub fn apply(&self, code: &str) -> String {
+        let mut edits: Vec<&Edit> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| (edit.span.begin(), edit.span.end()));
+        let mut out = String::with_capacity(code.len());
+        let mut copied = 0;
+        for edit in edits {
+            out.push_str(&code[copied..edit.span.begin() as usize]);
+            out.push_str(&edit.replacement);
+            copied = edit.span.end() as usize;
+        }
+        out.push_str(&code[copied..]);
+        out
+    }
+
+}
+
+// A message about the source, shared by the scanner and the parser.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+
+    pub fn new(severity: Severity, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Diagnostic {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Diagnostic {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+
+}
+
+impl From<ScanError> for Diagnostic {
+
+    fn from(error: ScanError) -> Diagnostic {
+        Diagnostic::error(error.message).with_primary(error.location, "")
+    }
+
+}
+
+// Renders `diagnostic` the way rustc does: a header, the location of the
+// primary label, the labelled source lines with underlines, the notes and
+// then each suggestion with the lines it changes. Escapes for a terminal
+// are added if `color` is set. Tabs print as single spaces so that the
+// underlines stay aligned.
+pub fn render(diagnostic: &Diagnostic, map: &SourceMap, color: bool) -> String {
+    let paint = |text: &str, style: &str| match color {
+        true => format!("\x1b[{}m{}\x1b[0m", style, text),
+        false => text.to_string(),
+    };
+    let severity = diagnostic.severity;
+    let mut out = String::new();
+
+    let head = match &diagnostic.code {
+        Some(code) => format!("{}[{}]", severity.name(), code),
+        None => severity.name().to_string(),
+    };
+    out.push_str(&paint(&head, severity.style()));
+    out.push_str(&paint(&format!(": {}", diagnostic.message), "1"));
+    out.push('\n');
+
+    let mut lines: Vec<u32> = diagnostic.labels.iter().map(|label| map.line(label.span.begin())).collect();
+    for suggestion in &diagnostic.suggestions {
+        lines.extend(suggestion.edits.iter().map(|edit| map.line(edit.span.begin())));
+    }
+    let width = lines.iter().max().map_or(1, |line| line.to_string().len());
+    let pad = " ".repeat(width);
+    let gutter = |line: Option<u32>| {
+        let number = line.map_or(pad.clone(), |line| format!("{:>width$}", line, width = width));
+        paint(&format!("{} |", number), "1;34")
+    };
+
+    let primary = diagnostic.primary_span().or_else(|| diagnostic.labels.first().map(|label| label.span));
+    if let Some(span) = primary {
+        let (line, column) = map.line_column(span.begin());
+        out.push_str(&format!("{}{} {}:{}:{}\n", pad, paint("-->", "1;34"), map.name(), line, column));
+    }
+
+    if !diagnostic.labels.is_empty() {
+        out.push_str(&gutter(None));
+        out.push('\n');
+        let mut labelled: Vec<u32> = diagnostic.labels.iter().map(|label| map.line(label.span.begin())).collect();
+        labelled.sort_unstable();
+        labelled.dedup();
+        let mut previous = None;
+        for line in labelled {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                out.push_str(&paint("...", "1;34"));
+                out.push('\n');
+            }
+            previous = Some(line);
+            out.push_str(&format!("{} {}\n", gutter(Some(line)), map.line_text(line).replace('\t', " ")));
+            let line_span = map.line_span(line);
+            let mut labels: Vec<&Label> = diagnostic.labels.iter().filter(|label| map.line(label.span.begin()) == line).collect();
+            labels.sort_by_key(|label| (label.span.begin(), !label.primary));
+            for label in labels {
+                // A span running past the line is underlined to its end.
+                let end = label.span.end().clamp(label.span.begin(), line_span.end());
+                let column = map.char_column(label.span.begin()) as usize;
+                let len = map.text()[label.span.begin() as usize..end as usize].chars().count().max(1);
+                let (mark, style) = match label.primary {
+                    true => ("^", severity.style()),
+                    false => ("-", "1;34"),
+                };
+                let mut underline = mark.repeat(len);
+                if !label.message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(&label.message);
+                }
+                out.push_str(&format!("{} {}{}\n", gutter(None), " ".repeat(column - 1), paint(&underline, style)));
+            }
+        }
+    }
+
+    for note in &diagnostic.notes {
+        out.push_str(&format!("{} {} {}\n", pad, paint("=", "1;34"), paint(&format!("note: {}", note), "1")));
+    }
+
+    for suggestion in &diagnostic.suggestions {
+        out.push_str(&paint("help", Severity::Help.style()));
+        out.push_str(&format!(": {}\n", suggestion.message));
+        let mut edited: Vec<u32> = suggestion.edits.iter().map(|edit| map.line(edit.span.begin())).collect();
+        edited.sort_unstable();
+        edited.dedup();
+        if edited.is_empty() {
+            continue;
+        }
+        out.push_str(&gutter(None));
+        out.push('\n');
+        for line in edited {
+            let line_span = map.line_span(line);
+            let mut edits: Vec<&Edit> = suggestion
+                .edits
+                .iter()
+                .filter(|edit| edit.span.begin() >= line_span.begin() && edit.span.end() <= line_span.end())
+                .collect();
+            edits.sort_by_key(|edit| edit.span.begin());
+            // The edited line, and under it what was put in.
+            let mut text = String::new();
+            let mut marks = String::new();
+            let mut copied = line_span.begin() as usize;
+            for edit in edits {
+                let kept = &map.text()[copied..edit.span.begin() as usize];
+                text.push_str(kept);
+                marks.push_str(&" ".repeat(kept.chars().count()));
+                text.push_str(&edit.replacement);
+                let mark = if edit.span.is_empty() { "+" } else { "~" };
+                marks.push_str(&mark.repeat(edit.replacement.chars().count()));
+                copied = edit.span.end() as usize;
+            }
+            text.push_str(&map.text()[copied..line_span.end() as usize]);
+            out.push_str(&format!("{} {}\n", gutter(Some(line)), text.replace('\t', " ")));
+            if !marks.trim().is_empty() {
+                out.push_str(&format!("{} {}\n", gutter(None), paint(marks.trim_end(), "1;32")));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_labels_and_notes() {
+        let map = SourceMap::new("main.mt", "var x = 1\nx(\n  y,\n\tz +)\n");
+        let diagnostic = Diagnostic::error("unexpected `)`")
+            .with_code("P0001")
+            .with_primary(Span::new(22, 23), "expected an expression")
+            .with_secondary(Span::new(11, 12), "call starts here")
+            .with_secondary(Span::new(21, 22), "")
+            .with_note("an operator needs two operands");
+        assert_eq!(
+            render(&diagnostic, &map, false),
+            "error[P0001]: unexpected `)`\n\
+             \x20--> main.mt:4:5\n\
+             \x20 |\n\
+             2 | x(\n\
+             \x20 |  - call starts here\n\
+             ...\n\
+             4 |  z +)\n\
+             \x20 |    -\n\
+             \x20 |     ^ expected an expression\n\
+             \x20 = note: an operator needs two operands\n"
+        );
+    }
+
+    #[test]
+    fn renders_suggestions() {
+        let map = SourceMap::new("a.mt", "a = 1 b = 2\nf(x y)");
+        let suggestion = Suggestion::insert("add `;` here", 5, ";", Applicability::MachineApplicable);
+        let diagnostic = Diagnostic::error("expected `;`").with_primary(Span::new(6, 7), "").with_suggestion(suggestion);
+        assert_eq!(
+            render(&diagnostic, &map, false),
+            "error: expected `;`\n\
+             \x20--> a.mt:1:7\n\
+             \x20 |\n\
+             1 | a = 1 b = 2\n\
+             \x20 |       ^\n\
+             help: add `;` here\n\
+             \x20 |\n\
+             1 | a = 1; b = 2\n\
+             \x20 |      +\n"
+        );
+        let replace = Suggestion {
+            message: "separate the arguments".to_string(),
+            edits: vec![Edit { span: Span::new(15, 16), replacement: ", ".to_string() }],
+            applicability: Applicability::MaybeIncorrect,
+        };
+        let rendered = render(&Diagnostic::error("expected `,`").with_suggestion(replace), &map, false);
+        assert!(rendered.ends_with("2 | f(x, y)\n  |    ~~\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn renders_colors() {
+        let map = SourceMap::new("a.mt", "@");
+        let diagnostic = Diagnostic::new(Severity::Warning, "odd").with_primary(Span::new(0, 1), "here");
+        let rendered = render(&diagnostic, &map, true);
+        assert!(rendered.starts_with("\x1b[1;33mwarning\x1b[0m\x1b[1m: odd\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;33m^ here\x1b[0m"));
+    }
+
+    #[test]
+    fn applies_suggestions() {
+        let suggestion = Suggestion {
+            message: "rename".to_string(),
+            edits: vec![
+                Edit { span: Span::new(9, 10), replacement: "count".to_string() },
+                Edit { span: Span::new(0, 1), replacement: "count".to_string() },
+                Edit { span: Span::new(6, 6), replacement: "+ ".to_string() },
+            ],
+            applicability: Applicability::MachineApplicable,
+        };
+        assert_eq!(suggestion.apply("n = n 1; n"), "count = n + 1; count");
+    }
+
+    #[test]
+    fn converts_scan_errors() {
+        let error = ScanError { location: Span::new(4, 9), message: "unterminated string literal".to_string() };
+        let diagnostic = Diagnostic::from(error);
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.primary_span(), Some(Span::new(4, 9)));
+        let map = SourceMap::new("s.mt", "1 + 'open");
+        assert!(render(&diagnostic, &map, false).contains("1 + 'open\n  |     ^^^^^\n"));
+    }
+}
@@ -0,0 +1,515 @@
+// Serialized token streams for tools and golden tests.
+//
+// JSON Lines: one object per token, keys always in this order and only
+// "value" optional (present for tokens that carry a payload):
+//
+//   {"kind":"Identifier","value":"s","text":"s","begin":7,"end":8,"line":1,"column":8,"trivia":" "}
+//
+// The last record of a stream is always the Eos token, whose trivia holds
+// whatever followed the last real token. Concatenating trivia and text of
+// every record gives back the original source.
+//
+// Binary: the magic "MTOK", a version byte, then per record a kind byte
+// followed by LEB128 numbers and length-prefixed UTF-8 strings.
+
+use std::fmt;
+use crate::scanner::{Config, Scanner, Span};
+use crate::token::{Symbol, Token, FIXED_TOKENS};
+
+const MAGIC: &[u8; 4] = b"MTOK";
+const VERSION: u8 = 1;
+
+// Binary kind bytes for tokens with a payload; fixed tokens use their
+// index in FIXED_TOKENS.
+const KIND_NUMBER: u8 = 200;
+const KIND_STRING: u8 = 201;
+const KIND_IDENTIFIER: u8 = 202;
+const KIND_PRIVATE_NAME: u8 = 203;
+const KIND_CHAR_LITERAL: u8 = 204;
+const KIND_COMMENT: u8 = 205;
+const KIND_CUSTOM_OPERATOR: u8 = 206;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenRecord {
+    pub token: Token,
+    pub location: Span,
+    pub line: u32,
+    pub column: u32,
+    pub text: String,
+    // Whitespace and comments between the previous token and this one.
+    pub trivia: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpError {
+    // One-based line for JSON input, byte offset for binary input.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+pub fn records(code: &str, config: Config) -> Vec<TokenRecord> {
+    let mut s = Scanner::with_config(code, 0, config);
+    s.init();
+    let mut records = Vec::new();
+    let mut position = 0;
+    loop {
+        let token = s.next();
+        let location = s.location();
+        let (line, column) = s.line_column(location.begin());
+        let begin = location.begin() as usize;
+        let end = location.end() as usize;
+        records.push(TokenRecord {
+            token: token.clone(),
+            location,
+            line,
+            column,
+            text: code[begin..end].to_string(),
+            trivia: code[position..begin].to_string(),
+        });
+        position = end;
+        if token == Token::Eos {
+            return records;
+        }
+    }
+}
+
+pub fn to_json_lines(records: &[TokenRecord]) -> String {
+    let mut out = String::new();
+    for record in records {
+        out.push_str("{\"kind\":");
+        write_json_string(&mut out, &record.token.to_string());
+        if let Some(value) = payload(&record.token) {
+            out.push_str(",\"value\":");
+            write_json_string(&mut out, &value);
+        }
+        out.push_str(",\"text\":");
+        write_json_string(&mut out, &record.text);
+        out.push_str(&format!(
+            ",\"begin\":{},\"end\":{},\"line\":{},\"column\":{},\"trivia\":",
+            record.location.begin(),
+            record.location.end(),
+            record.line,
+            record.column
+        ));
+        write_json_string(&mut out, &record.trivia);
+        out.push_str("}\n");
+    }
+    out
+}
+
+pub fn from_json_lines(input: &str) -> Result<Vec<TokenRecord>, DumpError> {
+    let mut records = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |message: String| DumpError { position: index + 1, message };
+        let fields = JsonObject::parse(line).map_err(error)?;
+        let kind = fields.string("kind").map_err(error)?;
+        let value = fields.optional_string("value").map_err(error)?;
+        let token = token_from_kind(&kind, value.as_deref()).map_err(error)?;
+        records.push(TokenRecord {
+            token,
+            location: Span::new(fields.number("begin").map_err(error)?, fields.number("end").map_err(error)?),
+            line: fields.number("line").map_err(error)?,
+            column: fields.number("column").map_err(error)?,
+            text: fields.string("text").map_err(error)?,
+            trivia: fields.string("trivia").map_err(error)?,
+        });
+    }
+    Ok(records)
+}
+
+pub fn to_binary(records: &[TokenRecord]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    for record in records {
+        out.push(kind_byte(&record.token));
+        if let Some(value) = payload(&record.token) {
+            write_binary_string(&mut out, &value);
+        }
+        write_varint(&mut out, record.location.begin());
+        write_varint(&mut out, record.location.len());
+        write_varint(&mut out, record.line);
+        write_varint(&mut out, record.column);
+        write_binary_string(&mut out, &record.text);
+        write_binary_string(&mut out, &record.trivia);
+    }
+    out
+}
+
+pub fn from_binary(input: &[u8]) -> Result<Vec<TokenRecord>, DumpError> {
+    let mut reader = BinaryReader { input, position: 0 };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(reader.error("not a token dump"));
+    }
+    if reader.byte()? != VERSION {
+        return Err(reader.error("unsupported token dump version"));
+    }
+    let mut records = Vec::new();
+    while reader.position < input.len() {
+        let kind = reader.byte()?;
+        let value = if has_payload(kind) { Some(reader.string()?) } else { None };
+        let token = token_from_byte(kind, value.as_deref()).map_err(|message| reader.error(&message))?;
+        let begin = reader.varint()?;
+        let end = begin.checked_add(reader.varint()?).ok_or_else(|| reader.error("span out of range"))?;
+        records.push(TokenRecord {
+            token,
+            location: Span::new(begin, end),
+            line: reader.varint()?,
+            column: reader.varint()?,
+            text: reader.string()?,
+            trivia: reader.string()?,
+        });
+    }
+    Ok(records)
+}
+
+pub(crate) fn payload(token: &Token) -> Option<String> {
+    match token {
+        Token::Number(_) | Token::String(_) | Token::Identifier(_) | Token::CharLiteral(_) => {
+            Some(token.clone().value())
+        },
+        Token::PrivateName(name) => Some(name.iter().collect()),
+        Token::Comment(len) => Some(len.to_string()),
+        Token::CustomOperator(symbol) => Some(symbol.0.to_string()),
+        _ => None,
+    }
+}
+
+pub(crate) fn kind_byte(token: &Token) -> u8 {
+    match token {
+        Token::Number(_) => KIND_NUMBER,
+        Token::String(_) => KIND_STRING,
+        Token::Identifier(_) => KIND_IDENTIFIER,
+        Token::PrivateName(_) => KIND_PRIVATE_NAME,
+        Token::CharLiteral(_) => KIND_CHAR_LITERAL,
+        Token::Comment(_) => KIND_COMMENT,
+        Token::CustomOperator(_) => KIND_CUSTOM_OPERATOR,
+        _ => FIXED_TOKENS.iter().position(|fixed| fixed == token).unwrap() as u8,
+    }
+}
+
+pub(crate) fn has_payload(kind: u8) -> bool {
+    kind >= KIND_NUMBER
+}
+
+pub(crate) fn token_from_byte(kind: u8, value: Option<&str>) -> Result<Token, String> {
+    let name = match kind {
+        KIND_NUMBER => "Number",
+        KIND_STRING => "String",
+        KIND_IDENTIFIER => "Identifier",
+        KIND_PRIVATE_NAME => "PrivateName",
+        KIND_CHAR_LITERAL => "CharLiteral",
+        KIND_COMMENT => "Comment",
+        KIND_CUSTOM_OPERATOR => "CustomOperator",
+        _ => return FIXED_TOKENS.get(kind as usize).cloned().ok_or_else(|| "unknown token kind".to_string()),
+    };
+    token_from_kind(name, value)
+}
+
+fn token_from_kind(kind: &str, value: Option<&str>) -> Result<Token, String> {
+    let chars = || match value {
+        Some(value) => Ok(value.chars().collect()),
+        None => Err(format!("missing value for {}", kind)),
+    };
+    match kind {
+        "Number" => Ok(Token::Number(chars()?)),
+        "String" => Ok(Token::String(chars()?)),
+        "Identifier" => Ok(Token::Identifier(chars()?)),
+        "PrivateName" => Ok(Token::PrivateName(chars()?)),
+        "CharLiteral" => match chars()?[..] {
+            [ch] => Ok(Token::CharLiteral(ch)),
+            _ => Err("character literal value must be one character".to_string()),
+        },
+        "Comment" => value
+            .and_then(|value| value.parse().ok())
+            .map(Token::Comment)
+            .ok_or_else(|| "comment value must be its length".to_string()),
+        "CustomOperator" => value
+            .and_then(|value| value.parse().ok())
+            .map(|index| Token::CustomOperator(Symbol(index)))
+            .ok_or_else(|| "custom operator value must be its symbol".to_string()),
+        _ => FIXED_TOKENS
+            .iter()
+            .find(|token| token.to_string() == kind)
+            .cloned()
+            .ok_or_else(|| format!("unknown token kind '{}'", kind)),
+    }
+}
+
+fn write_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // U+2028/2029 are line terminators for the scanner; escaping
+            // them keeps every record on one physical line.
+            ch if ch < ' ' || ch == '\u{2028}' || ch == '\u{2029}' => {
+                out.push_str(&format!("\\u{:04x}", ch as u32))
+            },
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(crate) fn write_binary_string(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub(crate) struct BinaryReader<'a> {
+    pub(crate) input: &'a [u8],
+    pub(crate) position: usize,
+}
+
+impl BinaryReader<'_> {
+
+    pub(crate) fn error(&self, message: &str) -> DumpError {
+        DumpError { position: self.position, message: message.to_string() }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&[u8], DumpError> {
+        if self.input.len() - self.position < len {
+            return Err(self.error("unexpected end of token dump"));
+        }
+        let bytes = &self.input[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, DumpError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u32, DumpError> {
+        let mut value: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("number too large"))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, DumpError> {
+        let len = self.varint()? as usize;
+        let bytes = self.bytes(len)?;
+        match std::str::from_utf8(bytes) {
+            Ok(value) => Ok(value.to_string()),
+            Err(_) => Err(self.error("invalid UTF-8 in token dump")),
+        }
+    }
+
+}
+
+// Just enough JSON to read back what to_json_lines() writes: one flat
+// object whose values are strings or non-negative integers.
+struct JsonObject {
+    fields: Vec<(String, JsonValue)>,
+}
+
+enum JsonValue {
+    String(String),
+    Number(u32),
+}
+
+impl JsonObject {
+
+    fn parse(line: &str) -> Result<JsonObject, String> {
+        let mut chars = line.trim().chars().peekable();
+        let mut fields = Vec::new();
+        expect(&mut chars, '{')?;
+        if chars.peek() == Some(&'}') {
+            chars.next();
+        } else {
+            loop {
+                skip_spaces(&mut chars);
+                let key = parse_json_string(&mut chars)?;
+                skip_spaces(&mut chars);
+                expect(&mut chars, ':')?;
+                skip_spaces(&mut chars);
+                let value = match chars.peek() {
+                    Some('"') => JsonValue::String(parse_json_string(&mut chars)?),
+                    Some(ch) if ch.is_ascii_digit() => {
+                        let mut digits = String::new();
+                        while let Some(digit) = chars.next_if(|ch| ch.is_ascii_digit()) {
+                            digits.push(digit);
+                        }
+                        JsonValue::Number(digits.parse().map_err(|_| format!("number out of range for '{}'", key))?)
+                    },
+                    _ => return Err(format!("unsupported value for '{}'", key)),
+                };
+                fields.push((key, value));
+                skip_spaces(&mut chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => return Err("expected ',' or '}'".to_string()),
+                }
+            }
+        }
+        if chars.next().is_some() {
+            return Err("trailing characters after object".to_string());
+        }
+        Ok(JsonObject { fields })
+    }
+
+    fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.fields.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+
+    fn optional_string(&self, key: &str) -> Result<Option<String>, String> {
+        match self.get(key) {
+            Some(JsonValue::String(value)) => Ok(Some(value.clone())),
+            Some(JsonValue::Number(_)) => Err(format!("'{}' must be a string", key)),
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<String, String> {
+        self.optional_string(key)?.ok_or_else(|| format!("missing '{}'", key))
+    }
+
+    fn number(&self, key: &str) -> Result<u32, String> {
+        match self.get(key) {
+            Some(JsonValue::Number(value)) => Ok(*value),
+            Some(JsonValue::String(_)) => Err(format!("'{}' must be a number", key)),
+            None => Err(format!("missing '{}'", key)),
+        }
+    }
+
+}
+
+type JsonChars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_spaces(chars: &mut JsonChars) {
+    while chars.next_if(|ch| ch.is_ascii_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut JsonChars, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(ch) if ch == expected => Ok(()),
+        _ => Err(format!("expected '{}'", expected)),
+    }
+}
+
+fn parse_json_string(chars: &mut JsonChars) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(value),
+            Some('\\') => {
+                let ch = match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let high = parse_hex4(chars)?;
+                        if (0xd800..0xdc00).contains(&high) {
+                            expect(chars, '\\')?;
+                            expect(chars, 'u')?;
+                            let low = parse_hex4(chars)?;
+                            let units = [high as u16, low as u16];
+                            char::decode_utf16(units).next().unwrap().map_err(|_| "invalid surrogate pair".to_string())?
+                        } else {
+                            char::from_u32(high).ok_or_else(|| "invalid \\u escape".to_string())?
+                        }
+                    },
+                    _ => return Err("invalid escape in string".to_string()),
+                };
+                value.push(ch);
+            },
+            Some(ch) => value.push(ch),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut JsonChars) -> Result<u32, String> {
+    let mut value = 0;
+    for _ in 0..4 {
+        let digit = chars.next().and_then(|ch| ch.to_digit(16)).ok_or_else(|| "invalid \\u escape".to_string())?;
+        value = value * 16 + digit;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "#!/bin/monty\nstring s = 'h\\u00e9\"';\n  if (s.#n >= 1e3) /* c */ { x++ }\u{2028}@a\n";
+
+    #[test]
+    fn records_are_lossless() {
+        let records = records(CODE, Config::default());
+        let text: String = records.iter().map(|record| format!("{}{}", record.trivia, record.text)).collect();
+        assert_eq!(text, CODE);
+        assert_eq!(records.last().unwrap().token, Token::Eos);
+        let s = &records[1];
+        assert_eq!(s.token, Token::Identifier(vec!['s']));
+        assert_eq!((s.line, s.column), (2, 8));
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let records = records(CODE, Config::default());
+        let json = to_json_lines(&records);
+        assert_eq!(json.lines().count(), records.len());
+        assert_eq!(
+            json.lines().nth(3).unwrap(),
+            r#"{"kind":"String","value":"hé\"","text":"'h\\u00e9\"'","begin":24,"end":34,"line":2,"column":12,"trivia":" "}"#
+        );
+        assert_eq!(from_json_lines(&json).unwrap(), records);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let config = Config { char_literals: true, comments: true, ..Default::default() };
+        let records = records(&format!("{}'c'", CODE), config);
+        assert!(records.iter().any(|record| matches!(record.token, Token::Comment(7))));
+        assert!(records.iter().any(|record| record.token == Token::CharLiteral('c')));
+        assert_eq!(from_binary(&to_binary(&records)).unwrap(), records);
+    }
+
+    #[test]
+    fn rejects_malformed_dumps() {
+        let error = from_json_lines("{\"kind\":\"Eos\"}\n{\"kind\":\"Nope\"}").err().unwrap();
+        assert_eq!(error.position, 1);
+        let error = from_json_lines("{\"kind\":\"Nope\",\"text\":\"\"}").err().unwrap();
+        assert_eq!(error.message, "unknown token kind 'Nope'");
+        let mut binary = to_binary(&records("x", Config::default()));
+        binary.truncate(binary.len() - 1);
+        assert!(from_binary(&binary).is_err());
+        assert!(from_binary(b"MTOK\x09").is_err());
+    }
+}
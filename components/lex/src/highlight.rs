@@ -0,0 +1,214 @@
+use crate::scanner::{Config, Scanner, Span};
+use crate::token::Token;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Keyword,
+    ReservedWord,
+    Literal,
+    Operator,
+    Punctuation,
+    Annotation,
+    Comment,
+    Identifier,
+    Invalid,
+}
+
+impl Class {
+
+    pub fn of(token: &Token) -> Class {
+        match token {
+            Token::Comment(_) => Class::Comment,
+            Token::Identifier(_) | Token::PrivateName(_) => Class::Identifier,
+            Token::At => Class::Annotation,
+            Token::CustomOperator(_) => Class::Operator,
+            Token::Illegal => Class::Invalid,
+            _ if token.is_keyword() => Class::Keyword,
+            _ if token.is_reserved_word() => Class::ReservedWord,
+            _ if token.is_literal() => Class::Literal,
+            _ if token.is_assignment_op()
+                || token.is_binary_op()
+                || token.is_compare_op()
+                || token.is_unary_op()
+                || token.is_count_op()
+                || token.is_bit_op()
+                || *token == Token::Conditional => Class::Operator,
+            _ => Class::Punctuation,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::ReservedWord => "reserved",
+            Class::Literal => "literal",
+            Class::Operator => "operator",
+            Class::Punctuation => "punctuation",
+            Class::Annotation => "annotation",
+            Class::Comment => "comment",
+            Class::Identifier => "identifier",
+            Class::Invalid => "invalid",
+        }
+    }
+
+}
+
+// Decides how each class of token looks. `ansi` returns the parameters of
+// an SGR escape (e.g. "1;34"), `css_class` the class put on the HTML span;
+// None leaves the text unstyled.
+pub trait Theme {
+
+    fn ansi(&self, class: Class) -> Option<&str>;
+
+    fn css_class(&self, class: Class) -> Option<String> {
+        Some(format!("monty-{}", class.name()))
+    }
+
+}
+
+pub struct DefaultTheme;
+
+impl Theme for DefaultTheme {
+
+    fn ansi(&self, class: Class) -> Option<&str> {
+        match class {
+            Class::Keyword => Some("1;35"),
+            Class::ReservedWord => Some("35"),
+            Class::Literal => Some("32"),
+            Class::Operator => Some("33"),
+            Class::Annotation => Some("36"),
+            Class::Comment => Some("2;37"),
+            Class::Invalid => Some("4;31"),
+            Class::Punctuation | Class::Identifier => None,
+        }
+    }
+
+}
+
+// Splits `code` into classified spans. Everything between the spans is
+// whitespace. Scanning errors never stop the walk: whatever could not be
+// lexed comes back as Class::Invalid.
+pub fn classify(code: &str) -> Vec<(Span, Class)> {
+    let config = Config { comments: true, ..Default::default() };
+    let mut s = Scanner::with_config(code, 0, config);
+    s.init();
+    let mut spans = Vec::new();
+    if let Some(shebang) = s.shebang() {
+        spans.push((shebang, Class::Comment));
+    }
+    loop {
+        let token = s.next();
+        if token == Token::Eos {
+            return spans;
+        }
+        spans.push((s.location(), Class::of(&token)));
+    }
+}
+
+pub fn to_ansi(code: &str, theme: &dyn Theme) -> String {
+    render(code, |out, text, class| match class.and_then(|class| theme.ansi(class)) {
+        Some(style) => {
+            out.push_str("\x1b[");
+            out.push_str(style);
+            out.push('m');
+            out.push_str(text);
+            out.push_str("\x1b[0m");
+        },
+        None => out.push_str(text),
+    })
+}
+
+pub fn to_html(code: &str, theme: &dyn Theme) -> String {
+    render(code, |out, text, class| match class.and_then(|class| theme.css_class(class)) {
+        Some(css_class) => {
+            out.push_str("<span class=\"");
+            escape_html(out, &css_class);
+            out.push_str("\">");
+            escape_html(out, text);
+            out.push_str("</span>");
+        },
+        None => escape_html(out, text),
+    })
+}
+
+fn render(code: &str, mut emit: impl FnMut(&mut String, &str, Option<Class>)) -> String {
+    let mut out = String::with_capacity(code.len() * 2);
+    let mut position = 0;
+    for (span, class) in classify(code) {
+        let begin = span.begin() as usize;
+        let end = span.end() as usize;
+        emit(&mut out, &code[position..begin], None);
+        emit(&mut out, &code[begin..end], Some(class));
+        position = end;
+    }
+    emit(&mut out, &code[position..], None);
+    out
+}
+
+fn escape_html(out: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Plain;
+
+    impl Theme for Plain {
+        fn ansi(&self, _: Class) -> Option<&str> {
+            None
+        }
+    }
+
+    #[test]
+    fn classifies_tokens() {
+        let classes: Vec<Class> = classify("if (x <= 1) return 'a'; // done\nclass")
+            .into_iter()
+            .map(|(_, class)| class)
+            .collect();
+        assert_eq!(classes, vec![
+            Class::Keyword,
+            Class::Punctuation,
+            Class::Identifier,
+            Class::Operator,
+            Class::Literal,
+            Class::Punctuation,
+            Class::Keyword,
+            Class::Literal,
+            Class::Punctuation,
+            Class::Comment,
+            Class::ReservedWord,
+        ]);
+    }
+
+    #[test]
+    fn renders_ansi_and_html() {
+        assert_eq!(to_ansi("x = 1", &DefaultTheme), "x \x1b[33m=\x1b[0m \x1b[32m1\x1b[0m");
+        assert_eq!(to_ansi("x = 1", &Plain), "x = 1");
+        assert_eq!(
+            to_html("a<b", &DefaultTheme),
+            "<span class=\"monty-identifier\">a</span>\
+             <span class=\"monty-operator\">&lt;</span>\
+             <span class=\"monty-identifier\">b</span>"
+        );
+    }
+
+    #[test]
+    fn keeps_invalid_input() {
+        let code = "#!/bin/monty\nx = \"open /* \u{1}";
+        let html = to_html(code, &DefaultTheme);
+        assert!(html.starts_with("<span class=\"monty-comment\">#!/bin/monty</span>\n"));
+        assert!(html.ends_with("<span class=\"monty-invalid\">&quot;open /* \u{1}</span>"));
+        assert_eq!(to_ansi(code, &Plain), code);
+    }
+}
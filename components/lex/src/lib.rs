@@ -1,2 +1,9 @@
 pub mod token;
-pub mod scanner;
\ No newline at end of file
+pub mod scanner;
+pub mod source;
+pub mod diagnostic;
+pub mod highlight;
+pub mod dump;
+pub mod preprocess;
+pub mod parallel;
+pub mod cache;
\ No newline at end of file
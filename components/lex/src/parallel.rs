@@ -0,0 +1,184 @@
+// Lexing a large source on several threads.
+//
+// A quick pre-scan finds line breaks that are outside strings and block
+// comments. No token can span such a break, so every chunk between two of
+// them can be scanned on its own, with its scanner started at the chunk's
+// offset to keep spans global. The records of all chunks are then stitched
+// together and come out identical to dump::records() on the whole source.
+
+use std::thread;
+use crate::dump::{self, TokenRecord};
+use crate::scanner::{is_line_terminator, Config, Scanner};
+use crate::token::Token;
+
+// Where a chunk may start, and how many lines precede it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplitPoint {
+    pub position: usize,
+    pub line: u32,
+}
+
+// Picks at most `chunks - 1` split points, roughly evenly spaced. The
+// pre-scan follows the scanner's rules for where strings and comments end,
+// including escaped line breaks in strings.
+pub fn split_points(code: &str, chunks: usize) -> Vec<SplitPoint> {
+    let target = code.len() / chunks.max(1) + 1;
+    let mut points = Vec::new();
+    let mut next_split = target;
+    let mut line = 0;
+    let mut chars = code.char_indices().peekable();
+    // Skipped like the scanner's init() does, so "#!" is not taken for an
+    // operator below.
+    if code.starts_with("\u{feff}#!") || code.starts_with("#!") {
+        while chars.next_if(|&(_, ch)| !is_line_terminator(ch)).is_some() {}
+    }
+    while let Some((_, ch)) = chars.next() {
+        match ch {
+            '"' | '\'' => {
+                while let Some((_, inner)) = chars.next_if(|&(_, inner)| !is_line_terminator(inner)) {
+                    if inner == ch {
+                        break;
+                    }
+                    if inner == '\\' {
+                        // The escaped character may be a line break, which
+                        // then belongs to the string.
+                        if let Some((_, escaped)) = chars.next() {
+                            if is_line_terminator(escaped) {
+                                if escaped == '\r' {
+                                    chars.next_if(|&(_, ch)| ch == '\n');
+                                }
+                                line += 1;
+                            }
+                        }
+                    }
+                }
+            },
+            '/' if chars.next_if(|&(_, ch)| ch == '/').is_some() => {
+                while chars.next_if(|&(_, ch)| !is_line_terminator(ch)).is_some() {}
+            },
+            '/' if chars.next_if(|&(_, ch)| ch == '*').is_some() => {
+                while let Some((_, inner)) = chars.next() {
+                    if inner == '\r' {
+                        chars.next_if(|&(_, ch)| ch == '\n');
+                    }
+                    if is_line_terminator(inner) {
+                        line += 1;
+                    }
+                    if inner == '*' && chars.next_if(|&(_, ch)| ch == '/').is_some() {
+                        break;
+                    }
+                }
+            },
+            _ if is_line_terminator(ch) => {
+                if ch == '\r' {
+                    chars.next_if(|&(_, ch)| ch == '\n');
+                }
+                line += 1;
+                let position = chars.peek().map_or(code.len(), |&(position, _)| position);
+                // A chunk starting with these would have them taken for a
+                // byte order mark or shebang.
+                let unsafe_start = matches!(chars.peek(), Some((_, '#')) | Some((_, '\u{feff}')) | None);
+                if position >= next_split && !unsafe_start {
+                    points.push(SplitPoint { position, line });
+                    next_split = position + target;
+                    if points.len() + 1 >= chunks {
+                        return points;
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    points
+}
+
+pub fn records(code: &str, config: &Config, chunks: usize) -> Vec<TokenRecord> {
+    // The pre-scan does not know about custom operators, so one that
+    // contains a comment opener could hide a real token boundary from it.
+    let comment_like = config
+        .operators
+        .iter()
+        .any(|operator| operator.spelling.contains("//") || operator.spelling.contains("/*"));
+    let points = if comment_like { Vec::new() } else { split_points(code, chunks) };
+    if points.is_empty() {
+        return dump::records(code, config.clone());
+    }
+    let mut bounds = vec![SplitPoint { position: 0, line: 0 }];
+    bounds.extend(points);
+    let results: Vec<Vec<TokenRecord>> = thread::scope(|scope| {
+        let handles: Vec<_> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, &begin)| {
+                let end = bounds.get(index + 1).map_or(code.len(), |point| point.position);
+                scope.spawn(move || chunk_records(code, begin, end, config.clone()))
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let count = results.len();
+    let mut records = Vec::new();
+    let mut trivia = String::new();
+    for (index, mut chunk) in results.into_iter().enumerate() {
+        // Each chunk but the last ends with an Eos record holding its
+        // trailing trivia, which belongs in front of the next chunk's first
+        // token.
+        chunk[0].trivia.insert_str(0, &trivia);
+        if index + 1 < count {
+            trivia = chunk.pop().unwrap().trivia;
+        }
+        records.append(&mut chunk);
+    }
+    records
+}
+
+fn chunk_records(code: &str, begin: SplitPoint, end: usize, config: Config) -> Vec<TokenRecord> {
+    let mut s = Scanner::with_config(&code[begin.position..end], begin.position as u32, config);
+    s.init();
+    let mut records = Vec::new();
+    let mut position = begin.position;
+    loop {
+        let token = s.next();
+        let location = s.location();
+        let (line, column) = s.line_column(location.begin());
+        let first = location.begin() as usize;
+        let last = location.end() as usize;
+        records.push(TokenRecord {
+            token: token.clone(),
+            location,
+            line: line + begin.line,
+            column,
+            text: code[first..last].to_string(),
+            trivia: code[position..first].to_string(),
+        });
+        position = last;
+        if token == Token::Eos {
+            return records;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_outside_strings_and_comments() {
+        let code = "a\n'x\\\ny'\n/* \n */\nb // c\nd";
+        let positions: Vec<usize> = split_points(code, 100).into_iter().map(|point| point.position).collect();
+        assert_eq!(positions, vec![2, 9, 17, 24]);
+        let lines: Vec<u32> = split_points(code, 100).into_iter().map(|point| point.line).collect();
+        assert_eq!(lines, vec![1, 3, 5, 6]);
+    }
+
+    #[test]
+    fn matches_sequential_output() {
+        let line = "def f(x) { return 'a\\\nb' + x /* \r\n */ >>= 0x1f; } // done\r\n#!x\n";
+        let code = format!("#!/bin/monty\n{}\u{2028}", line.repeat(50));
+        let config = Config::default();
+        let sequential = dump::records(&code, config.clone());
+        for chunks in [1, 2, 3, 8, 1000] {
+            assert_eq!(records(&code, &config, chunks), sequential, "{} chunks", chunks);
+        }
+    }
+}
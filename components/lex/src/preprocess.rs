@@ -0,0 +1,670 @@
+// Conditional compilation and token macros, run between the scanner and
+// the parser.
+//
+// A directive is a '#' name at the start of a line and runs to the end of
+// that line:
+//
+//   #define NAME tokens...        object-like macro
+//   #define NAME(a, b) tokens...  function-like: '(' right after the name
+//   #undef NAME
+//   #if EXPR / #elif EXPR / #else / #endif
+//   #ifdef NAME / #ifndef NAME
+//
+// Conditions are integer expressions over the usual operators, with
+// `defined(NAME)` and macros expanded first; unknown names are 0. Any other
+// '#name' is left alone as a private name.
+
+use std::collections::HashMap;
+use crate::scanner::{ScanError, Scanner, Span};
+use crate::token::Token;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    // The macro name at the place it was expanded.
+    pub use_site: Span,
+    // The macro name in its #define, None for macros given to define().
+    pub definition: Option<Span>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreprocessedToken {
+    pub token: Token,
+    // Where the token's text is: in a macro body for tokens a macro
+    // produced, at the use site for arguments and predefined macros.
+    pub location: Span,
+    // Macros this token came out of, outermost first.
+    pub expansions: Vec<Expansion>,
+    // Macros not to expand this token as when it is rescanned: the ones it
+    // came out of, including those it was an argument of.
+    pub hide: Vec<String>,
+}
+
+impl PreprocessedToken {
+
+    fn new(token: Token, location: Span) -> PreprocessedToken {
+        PreprocessedToken { token, location, expansions: Vec::new(), hide: Vec::new() }
+    }
+
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Option<Vec<String>>,
+    body: Vec<PreprocessedToken>,
+    definition: Option<Span>,
+}
+
+#[derive(Clone, Copy)]
+struct Condition {
+    location: Span,
+    // Whether the enclosing region is being kept at all.
+    outer: bool,
+    // Whether the current branch is being kept.
+    active: bool,
+    // Whether some branch of this #if has already been kept.
+    taken: bool,
+    seen_else: bool,
+}
+
+pub struct Preprocessor<'a> {
+    scanner: Scanner<'a>,
+    macros: HashMap<String, Macro>,
+    conditions: Vec<Condition>,
+    pending: Vec<PreprocessedToken>,
+    errors: Vec<ScanError>,
+    line_start: bool,
+}
+
+impl<'a> Preprocessor<'a> {
+
+    // Takes a scanner on which init() has not been called yet.
+    pub fn new(mut scanner: Scanner<'a>) -> Preprocessor<'a> {
+        scanner.init();
+        Preprocessor {
+            scanner,
+            macros: HashMap::new(),
+            conditions: Vec::new(),
+            pending: Vec::new(),
+            errors: Vec::new(),
+            line_start: true,
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        let mut s = Scanner::new(value, 0);
+        s.init();
+        let mut body = Vec::new();
+        while s.peek() != Token::Eos {
+            body.push(PreprocessedToken::new(s.next(), Span::default()));
+        }
+        self.macros.insert(name.to_string(), Macro { params: None, body, definition: None });
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    pub fn scanner(&self) -> &Scanner<'a> {
+        &self.scanner
+    }
+
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> PreprocessedToken {
+        loop {
+            if let Some(token) = self.pending.pop() {
+                return token;
+            }
+            let line_start = self.line_start;
+            let token = self.scanner.next();
+            let location = self.scanner.location();
+            self.line_start = self.scanner.has_line_terminator_before_next();
+            if let Token::PrivateName(name) = &token {
+                if line_start && self.directive(&name.iter().collect::<String>(), location) {
+                    continue;
+                }
+            }
+            if token == Token::Eos {
+                if let Some(condition) = self.conditions.pop() {
+                    self.error(condition.location, "unterminated #if");
+                    self.conditions.clear();
+                }
+                return PreprocessedToken::new(token, location);
+            }
+            if !self.active() {
+                continue;
+            }
+            let name = match &token {
+                Token::Identifier(name) => name.iter().collect::<String>(),
+                _ => return PreprocessedToken::new(token, location),
+            };
+            let invocation = match self.macros.get(&name) {
+                None => return PreprocessedToken::new(token, location),
+                Some(Macro { params: Some(_), .. }) if self.scanner.peek() != Token::Lparen => {
+                    return PreprocessedToken::new(token, location);
+                },
+                Some(Macro { params: Some(_), .. }) => {
+                    let mut invocation = vec![PreprocessedToken::new(token, location)];
+                    self.read_arguments(&mut invocation);
+                    invocation
+                },
+                Some(_) => vec![PreprocessedToken::new(token, location)],
+            };
+            let mut errors = Vec::new();
+            let mut expanded = self.expand(invocation, &mut errors);
+            self.errors.append(&mut errors);
+            expanded.reverse();
+            self.pending = expanded;
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.conditions.last().is_none_or(|condition| condition.active)
+    }
+
+    // Tokens up to the end of the current line.
+    fn rest_of_line(&mut self) -> Vec<PreprocessedToken> {
+        let mut tokens = Vec::new();
+        while !self.scanner.has_line_terminator_before_next() && self.scanner.peek() != Token::Eos {
+            let token = self.scanner.next();
+            tokens.push(PreprocessedToken::new(token, self.scanner.location()));
+        }
+        self.line_start = true;
+        tokens
+    }
+
+    // Pulls a parenthesized argument list for a function-like macro from
+    // the scanner, which may span several lines.
+    fn read_arguments(&mut self, invocation: &mut Vec<PreprocessedToken>) {
+        let mut depth = 0;
+        loop {
+            let token = self.scanner.next();
+            match token {
+                Token::Lparen => depth += 1,
+                Token::Rparen => depth -= 1,
+                Token::Eos => return,
+                _ => {}
+            }
+            invocation.push(PreprocessedToken::new(token, self.scanner.location()));
+            if depth == 0 {
+                self.line_start = self.scanner.has_line_terminator_before_next();
+                return;
+            }
+        }
+    }
+
+    // Returns false if `name` is not a directive.
+    fn directive(&mut self, name: &str, location: Span) -> bool {
+        match name {
+            "define" | "undef" | "if" | "ifdef" | "ifndef" | "elif" | "else" | "endif" => {},
+            _ => return false,
+        }
+        let line = self.rest_of_line();
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let outer = self.active();
+                let keep = outer && match name {
+                    "if" => self.evaluate(line, location),
+                    _ => {
+                        let defined = self.macro_name(&line, location).is_some_and(|name| self.is_defined(&name));
+                        defined == (name == "ifdef")
+                    }
+                };
+                self.conditions.push(Condition { location, outer, active: keep, taken: keep, seen_else: false });
+            },
+            "elif" | "else" => {
+                let condition = match self.conditions.last().copied() {
+                    Some(condition) if !condition.seen_else => condition,
+                    Some(_) => {
+                        self.error(location, &format!("#{} after #else", name));
+                        return true;
+                    },
+                    None => {
+                        self.error(location, &format!("#{} without #if", name));
+                        return true;
+                    }
+                };
+                let keep = condition.outer && !condition.taken && (name == "else" || self.evaluate(line, location));
+                let last = self.conditions.last_mut().unwrap();
+                last.active = keep;
+                last.taken |= keep;
+                last.seen_else = name == "else";
+            },
+            "endif" => {
+                if self.conditions.pop().is_none() {
+                    self.error(location, "#endif without #if");
+                }
+            },
+            _ if !self.active() => {},
+            "define" => self.define_macro(line, location),
+            _ => {
+                if let Some(name) = self.macro_name(&line, location) {
+                    self.macros.remove(&name);
+                }
+            }
+        }
+        true
+    }
+
+    fn macro_name(&mut self, line: &[PreprocessedToken], location: Span) -> Option<String> {
+        match line.first().map(|token| &token.token) {
+            Some(Token::Identifier(name)) => Some(name.iter().collect()),
+            _ => {
+                self.error(location, "expected a macro name");
+                None
+            }
+        }
+    }
+
+    fn define_macro(&mut self, line: Vec<PreprocessedToken>, location: Span) {
+        let name = match self.macro_name(&line, location) {
+            Some(name) => name,
+            None => return,
+        };
+        let definition = line[0].location;
+        let mut body = line.into_iter().skip(1).peekable();
+        let mut params = None;
+        // Only a '(' directly after the name makes a function-like macro.
+        if body.peek().is_some_and(|token| token.token == Token::Lparen && token.location.begin() == definition.end()) {
+            body.next();
+            let mut names = Vec::new();
+            loop {
+                match body.next().map(|token| token.token) {
+                    Some(Token::Identifier(param)) => names.push(param.iter().collect()),
+                    Some(Token::Rparen) if names.is_empty() => break,
+                    _ => {
+                        self.error(location, "malformed macro parameter list");
+                        return;
+                    }
+                }
+                match body.next().map(|token| token.token) {
+                    Some(Token::Comma) => continue,
+                    Some(Token::Rparen) => break,
+                    _ => {
+                        self.error(location, "malformed macro parameter list");
+                        return;
+                    }
+                }
+            }
+            params = Some(names);
+        }
+        let body = body.collect();
+        self.macros.insert(name, Macro { params, body, definition: Some(definition) });
+    }
+
+    // Expands every macro in `tokens`, rescanning each expansion. A token is
+    // never expanded as a macro in its `hide` list, so self-referencing
+    // macros terminate and arguments, expanded before substitution, are not
+    // expanded again.
+    fn expand(&self, tokens: Vec<PreprocessedToken>, errors: &mut Vec<ScanError>) -> Vec<PreprocessedToken> {
+        let mut out = Vec::new();
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            index += 1;
+            let name = match &token.token {
+                Token::Identifier(name) => name.iter().collect::<String>(),
+                _ => {
+                    out.push(token.clone());
+                    continue;
+                }
+            };
+            let definition = match self.macros.get(&name) {
+                Some(definition) if !token.hide.contains(&name) => definition,
+                _ => {
+                    out.push(token.clone());
+                    continue;
+                }
+            };
+            let mut arguments = Vec::new();
+            if let Some(params) = &definition.params {
+                match split_arguments(&tokens[index..]) {
+                    Some((parsed, consumed)) if parsed.len() == params.len() || (params.is_empty() && parsed.len() == 1 && parsed[0].is_empty()) => {
+                        arguments = parsed;
+                        index += consumed;
+                    },
+                    Some((parsed, _)) => {
+                        let found = match parsed.as_slice() {
+                            [argument] if argument.is_empty() => 0,
+                            _ => parsed.len(),
+                        };
+                        let plural = if params.len() == 1 { "" } else { "s" };
+                        errors.push(ScanError {
+                            location: token.location,
+                            message: format!("macro '{}' expects {} argument{}, found {}", name, params.len(), plural, found),
+                        });
+                        out.push(token.clone());
+                        continue;
+                    },
+                    // Without a '(' the name is not a use of the macro.
+                    None if tokens.get(index).is_some_and(|next| next.token == Token::Lparen) => {
+                        errors.push(ScanError {
+                            location: token.location,
+                            message: format!("unterminated argument list for macro '{}'", name),
+                        });
+                        out.push(token.clone());
+                        continue;
+                    },
+                    None => {
+                        out.push(token.clone());
+                        continue;
+                    }
+                }
+            }
+            // As in C, arguments are expanded on their own before they are
+            // substituted, so a macro used in an argument of itself is
+            // expanded too. Operands of `#` and `##` would have to be
+            // substituted as written.
+            let arguments: Vec<Vec<PreprocessedToken>> =
+                arguments.into_iter().map(|argument| self.expand(argument, errors)).collect();
+            let mut expansions = token.expansions.clone();
+            expansions.push(Expansion {
+                name: name.clone(),
+                use_site: token.location,
+                definition: definition.definition,
+            });
+            let mut hide = token.hide.clone();
+            hide.push(name);
+            let mut body = Vec::new();
+            for item in &definition.body {
+                let param = match (&item.token, &definition.params) {
+                    (Token::Identifier(id), Some(params)) => {
+                        let id: String = id.iter().collect();
+                        params.iter().position(|param| *param == id)
+                    },
+                    _ => None,
+                };
+                match param {
+                    Some(param) => body.extend(arguments[param].iter().map(|argument| {
+                        let mut argument = argument.clone();
+                        for name in &hide {
+                            if !argument.hide.contains(name) {
+                                argument.hide.push(name.clone());
+                            }
+                        }
+                        argument
+                    })),
+                    None => body.push(PreprocessedToken {
+                        token: item.token.clone(),
+                        location: if definition.definition.is_some() { item.location } else { token.location },
+                        expansions: expansions.clone(),
+                        hide: hide.clone(),
+                    }),
+                }
+            }
+            out.extend(self.expand(body, errors));
+        }
+        out
+    }
+
+    fn evaluate(&mut self, line: Vec<PreprocessedToken>, location: Span) -> bool {
+        // Resolve defined(NAME) before macro expansion can rewrite NAME.
+        let mut tokens = Vec::new();
+        let mut index = 0;
+        while index < line.len() {
+            if line[index].token == Token::Identifier("defined".chars().collect()) {
+                let (name, consumed) = match (line.get(index + 1).map(|t| &t.token), line.get(index + 2).map(|t| &t.token), line.get(index + 3).map(|t| &t.token)) {
+                    (Some(Token::Identifier(name)), _, _) => (name.clone(), 2),
+                    (Some(Token::Lparen), Some(Token::Identifier(name)), Some(Token::Rparen)) => (name.clone(), 4),
+                    _ => {
+                        self.error(location, "expected a macro name after 'defined'");
+                        return false;
+                    }
+                };
+                let defined = self.macros.contains_key(&name.iter().collect::<String>());
+                let value = if defined { '1' } else { '0' };
+                tokens.push(PreprocessedToken::new(Token::Number(vec![value]), line[index].location));
+                index += consumed;
+            } else {
+                tokens.push(line[index].clone());
+                index += 1;
+            }
+        }
+        let mut errors = Vec::new();
+        let tokens: Vec<Token> = self.expand(tokens, &mut errors).into_iter().map(|token| token.token).collect();
+        self.errors.append(&mut errors);
+        let mut evaluator = Evaluator { tokens: &tokens, index: 0 };
+        match evaluator.expression(1) {
+            Ok(value) if evaluator.index == tokens.len() => value != 0,
+            Ok(_) => {
+                self.error(location, "unexpected token in #if condition");
+                false
+            },
+            Err(message) => {
+                self.error(location, &message);
+                false
+            }
+        }
+    }
+
+    fn error(&mut self, location: Span, message: &str) {
+        self.errors.push(ScanError { location, message: message.to_string() });
+    }
+
+}
+
+// Splits `(a, b(c, d), e)` at the top-level commas. Returns the arguments
+// and how many tokens the parenthesized list took, or None if `tokens`
+// does not start with a complete one.
+fn split_arguments(tokens: &[PreprocessedToken]) -> Option<(Vec<Vec<PreprocessedToken>>, usize)> {
+    if tokens.first()?.token != Token::Lparen {
+        return None;
+    }
+    let mut arguments = vec![Vec::new()];
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(1) {
+        match token.token {
+            Token::Lparen => depth += 1,
+            Token::Rparen if depth == 0 => return Some((arguments, index + 1)),
+            Token::Rparen => depth -= 1,
+            Token::Comma if depth == 0 => {
+                arguments.push(Vec::new());
+                continue;
+            },
+            _ => {}
+        }
+        arguments.last_mut().unwrap().push(token.clone());
+    }
+    None
+}
+
+// Precedence climbing over Token::precedence() for #if conditions.
+struct Evaluator<'t> {
+    tokens: &'t [Token],
+    index: usize,
+}
+
+impl Evaluator<'_> {
+
+    fn expression(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.tokens.get(self.index) {
+            let precedence = op.precedence();
+            // Comma, assignment and ?: (precedence 1-3) make no sense here.
+            if precedence < 4 || precedence < min_precedence {
+                break;
+            }
+            self.index += 1;
+            let right = self.expression(precedence + 1)?;
+            left = match op {
+                Token::Or => ((left != 0) || (right != 0)) as i64,
+                Token::And => ((left != 0) && (right != 0)) as i64,
+                Token::BitOr => left | right,
+                Token::BitXor => left ^ right,
+                Token::BitAnd => left & right,
+                Token::Eq => (left == right) as i64,
+                Token::Ne => (left != right) as i64,
+                Token::Lt => (left < right) as i64,
+                Token::Gt => (left > right) as i64,
+                Token::Lte => (left <= right) as i64,
+                Token::Gte => (left >= right) as i64,
+                Token::Shl => left.wrapping_shl(right as u32),
+                Token::Sar | Token::Shr => left.wrapping_shr(right as u32),
+                Token::Add => left.wrapping_add(right),
+                Token::Sub => left.wrapping_sub(right),
+                Token::Mul => left.wrapping_mul(right),
+                Token::Div | Token::Mod if right == 0 => return Err("division by zero in #if condition".to_string()),
+                Token::Div => left.wrapping_div(right),
+                Token::Mod => left.wrapping_rem(right),
+                _ => return Err(format!("unsupported operator {} in #if condition", op)),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.index).ok_or_else(|| "incomplete #if condition".to_string())?;
+        self.index += 1;
+        match token {
+            Token::Not => Ok((self.unary()? == 0) as i64),
+            Token::Sub => Ok(self.unary()?.wrapping_neg()),
+            Token::Add => self.unary(),
+            Token::BitNot => Ok(!self.unary()?),
+            Token::TrueLiteral => Ok(1),
+            Token::FalseLiteral | Token::Identifier(_) => Ok(0),
+            Token::Number(digits) => {
+                let text: String = digits.iter().collect();
+                let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                value.map_err(|_| format!("'{}' is not an integer", text))
+            },
+            Token::Lparen => {
+                let value = self.expression(1)?;
+                match self.tokens.get(self.index) {
+                    Some(Token::Rparen) => {
+                        self.index += 1;
+                        Ok(value)
+                    },
+                    _ => Err("expected ')' in #if condition".to_string()),
+                }
+            },
+            _ => Err(format!("unexpected {} in #if condition", token)),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &str, defines: &[(&str, &str)]) -> (Vec<PreprocessedToken>, Vec<ScanError>) {
+        let mut p = Preprocessor::new(Scanner::new(code, 0));
+        for (name, value) in defines {
+            p.define(name, value);
+        }
+        let mut tokens = Vec::new();
+        loop {
+            let token = p.next();
+            if token.token == Token::Eos {
+                return (tokens, p.errors().to_vec());
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn text(tokens: &[PreprocessedToken]) -> String {
+        tokens.iter().map(|token| token.token.clone().value()).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn selects_conditional_branches() {
+        let code = "#if defined(DEBUG) && LEVEL > 1\na\n#elif defined(RELEASE)\nb\n#else\nc\n#endif\nd";
+        assert_eq!(text(&run(code, &[("DEBUG", ""), ("LEVEL", "2")]).0), "a d");
+        assert_eq!(text(&run(code, &[("DEBUG", ""), ("LEVEL", "1")]).0), "c d");
+        assert_eq!(text(&run(code, &[("RELEASE", "")]).0), "b d");
+        let nested = "#ifdef A\n#if 0\nx\n#else\ny\n#endif\n#endif\n#ifndef A\nz\n#endif";
+        assert_eq!(text(&run(nested, &[("A", "")]).0), "y");
+        assert_eq!(text(&run(nested, &[]).0), "z");
+    }
+
+    #[test]
+    fn expands_macros_with_trace() {
+        let code = "#define ONE 1\n#define ADD(a, b) (a + b)\nx = ADD(ONE, y * 2);\nADD #secret";
+        let (tokens, errors) = run(code, &[]);
+        assert!(errors.is_empty());
+        assert_eq!(text(&tokens), "x = ( 1 + y * 2 ) ; ADD #secret");
+        // The '1' comes from ONE, expanded as an argument of ADD.
+        let one = &tokens[3];
+        assert_eq!(one.token, Token::Number(vec!['1']));
+        assert_eq!(one.location, Span::new(12, 13));
+        assert_eq!(one.expansions.len(), 1);
+        assert_eq!(one.expansions[0].name, "ONE");
+        assert_eq!(one.expansions[0].use_site, Span::new(48, 51));
+        assert_eq!(one.expansions[0].definition, Some(Span::new(8, 11)));
+        // The '+' comes from the body of ADD.
+        let plus = &tokens[4];
+        assert_eq!(plus.location, Span::new(35, 36));
+        assert_eq!(plus.expansions[0].name, "ADD");
+        assert_eq!(plus.expansions[0].use_site, Span::new(44, 47));
+        // Arguments keep their own location.
+        assert_eq!(tokens[5].location, Span::new(53, 54));
+        assert!(tokens[5].expansions.is_empty());
+    }
+
+    #[test]
+    fn expands_nested_invocations() {
+        let code = "#define ADD(a, b) (a + b)\nADD(ADD(1, 2), 3)";
+        assert_eq!(text(&run(code, &[]).0), "( ( 1 + 2 ) + 3 )");
+        let code = "#define A A + 1\n#define F(x) x\nF(A)";
+        assert_eq!(text(&run(code, &[]).0), "A + 1");
+        let code = "#define MAX(a, b) ((a) > (b) ? (a) : (b))\nMAX(MAX(x, y), z)";
+        let (tokens, errors) = run(code, &[]);
+        assert!(errors.is_empty());
+        assert!(!text(&tokens).contains("MAX"), "{}", text(&tokens));
+        // The inner '+' comes from the inner use of ADD.
+        let (tokens, _) = run("#define ADD(a, b) (a + b)\nADD(ADD(1, 2), 3)", &[]);
+        let names: Vec<&str> = tokens[3].expansions.iter().map(|expansion| expansion.name.as_str()).collect();
+        assert_eq!(names, ["ADD"]);
+        assert_eq!(tokens[3].expansions[0].use_site, Span::new(30, 33));
+    }
+
+    #[test]
+    fn stops_recursive_expansion() {
+        let code = "#define A B + A\n#define B A\nA";
+        assert_eq!(text(&run(code, &[]).0), "A + A");
+        let code = "#define F(x) x\n#undef F\nF(1)";
+        assert_eq!(text(&run(code, &[]).0), "F ( 1 )");
+    }
+
+    #[test]
+    fn reports_wrong_macro_arguments() {
+        let (tokens, errors) = run("#define F(x, y) x\nF(1)", &[]);
+        assert_eq!(text(&tokens), "F ( 1 )");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "macro 'F' expects 2 arguments, found 1");
+        assert_eq!(errors[0].location, Span::new(18, 19));
+        let (_, errors) = run("#define G(x) x\nG(1, 2) G()", &[]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "macro 'G' expects 1 argument, found 2");
+        let (_, errors) = run("#define F(x, y) x\nF()", &[]);
+        assert_eq!(errors[0].message, "macro 'F' expects 2 arguments, found 0");
+        let (tokens, errors) = run("#define F(x) x\nF(1, (2)", &[]);
+        assert_eq!(text(&tokens), "F ( 1 , ( 2 )");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unterminated argument list for macro 'F'");
+        let (tokens, errors) = run("#define F(x) x\nF + 1", &[]);
+        assert_eq!(text(&tokens), "F + 1");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn reports_unbalanced_directives() {
+        let (tokens, errors) = run("#else\nx\n#if 1\ny", &[]);
+        assert_eq!(text(&tokens), "x y");
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, vec!["#else without #if", "unterminated #if"]);
+        let (_, errors) = run("#if 1 / 0\n#endif", &[]);
+        assert_eq!(errors[0].message, "division by zero in #if condition");
+    }
+}
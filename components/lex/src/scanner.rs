@@ -1,52 +1,332 @@
 use std::str::Chars;
 use std::iter::Peekable;
-use crate::token::Token;
+use crate::token::{self, Symbol, Token};
 
-#[derive(Clone, Default)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub struct Span {
     begin: u32,
     end: u32,
 }
 
+impl Span {
+
+    pub fn new(begin: u32, end: u32) -> Span {
+        Span { begin, end }
+    }
+
+    pub fn begin(&self) -> u32 {
+        self.begin
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.begin
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.begin == self.end
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanError {
+    pub location: Span,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf, // "\n"
+    CrLf, // "\r\n"
+    Cr, // "\r"
+    LineSeparator, // U+2028
+    ParagraphSeparator, // U+2029
+}
+
+// How many of each line terminator the scanner has gone past.
+#[derive(Clone, Copy, Default)]
+struct LineEndings {
+    lf: u32,
+    crlf: u32,
+    cr: u32,
+    ls: u32,
+    ps: u32,
+}
+
+impl LineEndings {
+
+    fn count(&mut self, ending: LineEnding) {
+        match ending {
+            LineEnding::Lf => self.lf += 1,
+            LineEnding::CrLf => self.crlf += 1,
+            LineEnding::Cr => self.cr += 1,
+            LineEnding::LineSeparator => self.ls += 1,
+            LineEnding::ParagraphSeparator => self.ps += 1,
+        }
+    }
+
+    // Ties go to the earlier entry, so a file without any line breaks
+    // reports plain "\n".
+    fn dominant(&self) -> LineEnding {
+        let counts = [
+            (LineEnding::Lf, self.lf),
+            (LineEnding::CrLf, self.crlf),
+            (LineEnding::Cr, self.cr),
+            (LineEnding::LineSeparator, self.ls),
+            (LineEnding::ParagraphSeparator, self.ps),
+        ];
+        let mut best = counts[0];
+        for entry in counts {
+            if entry.1 > best.1 {
+                best = entry;
+            }
+        }
+        best.0
+    }
+
+}
+
+pub fn is_line_terminator(ch: char) -> bool {
+    matches!(ch, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
 #[derive(Clone, Default)]
 struct TokenInfo {
     token: Token,
     location: Span,
     literal: Span,
+    after_line_terminator: bool,
+}
+
+// Everything needed to put a scanner back where it was, without copying
+// anything that allocates: the current and lookahead tokens are kept as
+// their spans and scanned again from the source on rewind, and pending
+// errors and line starts as counts to truncate to. The scanner has no
+// modes (template or regular expression contexts) whose stack would need
+// saving; the next token depends only on the position and the Config.
+#[derive(Clone, Copy)]
+pub struct Checkpoint {
+    current: TokenSpans,
+    next: TokenSpans,
+    mark: Mark,
+}
+
+#[derive(Clone, Copy)]
+struct TokenSpans {
+    location: Span,
+    literal: Span,
+    after_line_terminator: bool,
+}
+
+impl TokenSpans {
+
+    fn of(info: &TokenInfo) -> TokenSpans {
+        TokenSpans { location: info.location, literal: info.literal, after_line_terminator: info.after_line_terminator }
+    }
+
+}
+
+// A position in the source with the errors and line starts found before it.
+#[derive(Clone, Copy)]
+struct Mark {
+    position: u32,
+    errors: usize,
+    lines: usize,
+    line_endings: LineEndings,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operator {
+    pub spelling: String,
+    // On the scale of Token::precedence().
+    pub precedence: u8,
+    pub associativity: Associativity,
+}
+
+// Options that change how source text is tokenized. Every option
+// defaults to the behaviour of a plain Scanner::new().
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Lex 'x' as a Token::CharLiteral instead of a one-character string.
+    pub char_literals: bool,
+    // Return comments as Token::Comment instead of skipping them.
+    pub comments: bool,
+    // Extra infix operators, indexed by their Symbol.
+    pub operators: Vec<Operator>,
+}
+
+impl Config {
+
+    // Operators are made of the punctuation characters below and may not
+    // respell a built-in token or start a comment. The scanner always takes
+    // the longest operator, built-in or custom, at any position.
+    pub fn add_operator(
+        &mut self,
+        spelling: &str,
+        precedence: u8,
+        associativity: Associativity,
+    ) -> Result<Symbol, String> {
+        if spelling.is_empty() || !spelling.chars().all(|ch| "!$%&*+-./:<=>?@^|~".contains(ch)) {
+            return Err(format!("'{}' is not a valid operator spelling", spelling));
+        }
+        if spelling.starts_with("//") || spelling.starts_with("/*") {
+            return Err(format!("'{}' would start a comment", spelling));
+        }
+        let mut s = Scanner::new(spelling, 0);
+        if s.scan_token() != Token::Illegal && s.position() as usize == spelling.len() {
+            return Err(format!("'{}' is a built-in token", spelling));
+        }
+        if self.operators.iter().any(|operator| operator.spelling == spelling) {
+            return Err(format!("'{}' is already defined", spelling));
+        }
+        self.operators.push(Operator {
+            spelling: spelling.to_string(),
+            precedence,
+            associativity,
+        });
+        Ok(Symbol(self.operators.len() as u32 - 1))
+    }
+
+    pub fn operator(&self, symbol: Symbol) -> &Operator {
+        &self.operators[symbol.0 as usize]
+    }
+
+}
+
+// One-based line and column of `position`, given the offsets at which
+// lines begin. Columns count bytes; every line and column number given out
+// for a position, in token dumps and messages alike, comes from here.
+pub fn line_column(line_starts: &[u32], position: u32) -> (u32, u32) {
+    let line = match line_starts.binary_search(&position) {
+        Ok(line) => line,
+        Err(line) => line - 1,
+    };
+    (line as u32 + 1, position - line_starts[line] + 1)
 }
 
 pub struct Scanner<'a> {
-    literals: Vec<String>,
+    config: Config,
+    code: &'a str,
+    // The position of the start of `code`.
+    base: u32,
     current: TokenInfo,
     next: TokenInfo,
     position: u32,
     peekable: Peekable<Chars<'a>>,
+    errors: Vec<ScanError>,
+    // Offsets at which each line begins; the first entry is the start
+    // position handed to new().
+    line_starts: Vec<u32>,
+    line_endings: LineEndings,
+    bom: bool,
+    shebang: Option<Span>,
 }
 
-impl Scanner<'_> {
+impl<'a> Scanner<'a> {
 
-    pub fn new<'a>(code: &'a str, position: u32) -> Scanner<'a> {
+    pub fn new(code: &'a str, position: u32) -> Scanner<'a> {
+        Scanner::with_config(code, position, Config::default())
+    }
+
+    pub fn with_config(code: &'a str, position: u32, config: Config) -> Scanner<'a> {
         Scanner {
-            literals: Vec::new(),
+            config,
+            code,
+            base: position,
             current: Default::default(),
             next: Default::default(),
-            position: position,
+            position,
             peekable: code.chars().peekable(),
+            errors: Vec::new(),
+            line_starts: vec![position],
+            line_endings: Default::default(),
+            bom: false,
+            shebang: None,
         }
     }
 
     pub fn init(&mut self) {
-
+        if self.c0() == Some('\u{feff}') {
+            self.bom = true;
+            self.advance();
+        }
+        let mut ahead = self.peekable.clone();
+        if ahead.next() == Some('#') && ahead.next() == Some('!') {
+            let begin = self.position;
+            while self.c0().is_some_and(|ch| !is_line_terminator(ch)) {
+                self.advance();
+            }
+            self.shebang = Some(Span::new(begin, self.position));
+        }
+        self.scan();
     }
 
     pub fn location(&self) -> Span {
-        self.current.location.clone()
+        self.current.location
     }
 
     pub fn peek_location(&self) -> Span {
-        self.next.location.clone()
+        self.next.location
+    }
+
+    pub fn literal_location(&self) -> Span {
+        self.current.literal
+    }
+
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    pub fn shebang(&self) -> Option<Span> {
+        self.shebang
+    }
+
+    // True if a line terminator (possibly inside a comment) separates the
+    // current token from the next one, as automatic semicolon insertion
+    // needs to know.
+    pub fn has_line_terminator_before_next(&self) -> bool {
+        self.next.after_line_terminator
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_endings.dominant()
+    }
+
+    pub fn line_starts(&self) -> &[u32] {
+        &self.line_starts
+    }
+
+    // One-based line and column (in bytes) of a position the scanner has
+    // already gone past.
+    pub fn line_column(&self, position: u32) -> (u32, u32) {
+        line_column(&self.line_starts, position)
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Token {
         self.current = self.next.clone();
         self.scan();
@@ -57,193 +337,498 @@ impl Scanner<'_> {
         self.next.token.clone()
     }
 
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { current: TokenSpans::of(&self.current), next: TokenSpans::of(&self.next), mark: self.mark() }
+    }
+
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.current = self.rescan(checkpoint.current);
+        self.next = self.rescan(checkpoint.next);
+        self.reset(checkpoint.mark);
+    }
+
+    // The token at `spans`, scanned again. Only the end of input is empty.
+    fn rescan(&mut self, spans: TokenSpans) -> TokenInfo {
+        let token = match spans.location.is_empty() {
+            true => Token::Eos,
+            false => {
+                self.reset(Mark { position: spans.location.begin, ..self.mark() });
+                self.next.location.begin = spans.location.begin;
+                self.next.literal = Span::new(spans.location.begin, spans.location.begin);
+                self.scan_token()
+            },
+        };
+        TokenInfo {
+            token,
+            location: spans.location,
+            literal: spans.literal,
+            after_line_terminator: spans.after_line_terminator,
+        }
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            position: self.position,
+            errors: self.errors.len(),
+            lines: self.line_starts.len(),
+            line_endings: self.line_endings,
+        }
+    }
+
+    // Moves back to `mark`, forgetting the errors and line starts found
+    // after it.
+    fn reset(&mut self, mark: Mark) {
+        self.position = mark.position;
+        self.peekable = self.code[(mark.position - self.base) as usize..].chars().peekable();
+        self.errors.truncate(mark.errors);
+        self.line_starts.truncate(mark.lines);
+        self.line_endings = mark.line_endings;
+    }
+
+    // A "\r\n" pair is consumed as a single character so that every
+    // line terminator moves to the next line exactly once.
     pub fn advance(&mut self) {
-        self.peekable.next();
+        if let Some(ch) = self.peekable.next() {
+            self.position += ch.len_utf8() as u32;
+            let ending = match ch {
+                '\n' => LineEnding::Lf,
+                '\r' if self.peekable.next_if_eq(&'\n').is_some() => {
+                    self.position += 1;
+                    LineEnding::CrLf
+                },
+                '\r' => LineEnding::Cr,
+                '\u{2028}' => LineEnding::LineSeparator,
+                '\u{2029}' => LineEnding::ParagraphSeparator,
+                _ => return,
+            };
+            self.line_endings.count(ending);
+            self.line_starts.push(self.position);
+        }
     }
 
     pub fn scan(&mut self) {
-        //let token = self.scan_token();
-        // let len = 0;
-        // while token == Token::Comment(len) {
-        //     self.skip_white_space();
-        //     self.next.location.begin = self.position;
-        //     token = self.scan_token()
-        // }
-        //self.current.token = token;
-
-        self.current.token = self.scan_token();
+        let lines = self.line_starts.len();
+        loop {
+            self.skip_white_space();
+            self.next.location.begin = self.position;
+            self.next.literal = Span::new(self.position, self.position);
+            let token = self.scan_token();
+            if let Token::Comment(_) = token {
+                if !self.config.comments {
+                    continue;
+                }
+            }
+            self.next.token = token;
+            self.next.location.end = self.position;
+            self.next.after_line_terminator = self.line_starts.len() > lines;
+            break;
+        }
     }
 
     fn skip_white_space(&mut self) {
-        while self.peekable.next_if(|&ch| ch == ' ').is_some() {
-            self.advance()
+        while self.c0().is_some_and(char::is_whitespace) {
+            self.advance();
         }
     }
 
     pub fn scan_token(&mut self) -> Token {
-        match self.peekable.peek() {
-            Some(&ch) => match ch {
-                '"' => self.scan_string(),
-                '\'' => self.scan_string(),
-                '<' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::Lte),
-                        Some('<') => self.select_if('=', Token::AssignShl, Token::Shl),
-                        None => Token::Eos,
-                        _ => Token::Lt,
-                    }
-                },
-                '>' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::Gte),
-                        Some('>') => {
-                            match self.peekable.next() {
-                                Some('=') => self.select(Token::AssignSar),
-                                Some('>') => self.select_if('=', Token::AssignShr, Token::Shr),
-                                _ => Token::Sar
-                            }
-                        },
-                        None => Token::Eos,
-                        _ => Token::Gt,
-                    }
-                },
-                '=' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::Eq),
-                        _ => Token::Assign
-                    }
-                },
-                '!' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::Ne),
-                        _ => Token::Not
-                    }
-                },
-                '+' => {
-                    match self.peekable.next() {
-                        Some('+') => self.select(Token::Inc),
-                        Some('=') => self.select(Token::AssignAdd),
-                        _ => Token::Add,
-                    }
-                },
-                '-' => {
-                    match self.peekable.next() {
-                        Some('-') => self.select(Token::Dec),
-                        Some('=') => self.select(Token::AssignSub),
-                        _ => Token::Sub,
-                    }
-                },
-                '*' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::AssignMul),
-                        _ => Token::Mul,
-                    }
-                },
-                '%' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::AssignMod),
-                        _ => Token::Mod,
-                    }
-                },
-                '/' => {
-                    match self.peekable.next() {
-                        Some('/') => self.skip_single_line_comment(),
-                        Some('*') => self.skip_multi_line_comment(),
-                        Some('=') => self.select(Token::AssignDiv),
-                        _ => Token::Div
-                    }
-                },
-                '&' => {
-                    match self.peekable.next() {
-                        Some('&') => self.select(Token::And),
-                        Some('=') => self.select(Token::AssignBitAnd),
-                        _ => Token::BitAnd
-                    }
-                },
-                '|' => {
-                    match self.peekable.next() {
-                        Some('|') => self.select(Token::Or),
-                        Some('=') => self.select(Token::AssignBitOr),
-                        _ => Token::BitOr
-                    }
-                },
-                '^' => {
-                    match self.peekable.next() {
-                        Some('=') => self.select(Token::AssignBitXor),
-                        _ => Token::BitXor
-                    }
-                },
-                '.' => {
-                    let digit = self.peekable.next().unwrap();
-                    if self.is_decimal_digit(digit) {
-                        return self.scan_number();
-                    }
-                    return Token::Period;
-                },
-                ':' => self.select(Token::Colon),
-                ';' => self.select(Token::Semicolon),
-                ',' => self.select(Token::Comma),
-                '(' => self.select(Token::Lparen),
-                ')' => self.select(Token::Rparen),
-                '[' => self.select(Token::Lbrack),
-                ']' => self.select(Token::Rbrack),
-                '{' => self.select(Token::Lbrace),
-                '}' => self.select(Token::Rbrace),
-                '?' => self.select(Token::Conditional),
-                '~' => self.select(Token::BitNot),
-                _ => {
-                    //println!("Default => ");
-                    let c0 = self.peekable.peek();
-                    if c0.unwrap().is_ascii() {
-                        //println!("Identifier");
-                        //if c0 in "a..z".range() {
-                            return self.scan_identifier()
-                        //}
-                        //if c0 in "0..1".range() {
-                        //    return self.scan_number();
-                        //}
-                    } else if c0 == None {
-                        //println!("Eos");
-                        return Token::Eos;
-                    } else {
-                        //println!("Illegal");
-                        return Token::Illegal;
-                    }
+        if let Some((symbol, len)) = self.match_custom_operator() {
+            let mark = self.mark();
+            let token = self.scan_builtin_token();
+            if self.position - mark.position >= len {
+                return token;
+            }
+            self.reset(mark);
+            let end = self.position + len;
+            while self.position < end {
+                self.advance();
+            }
+            return Token::CustomOperator(symbol);
+        }
+        self.scan_builtin_token()
+    }
+
+    // The longest custom operator starting at the current position, with
+    // its length in bytes.
+    fn match_custom_operator(&self) -> Option<(Symbol, u32)> {
+        let mut best: Option<(Symbol, u32)> = None;
+        for (index, operator) in self.config.operators.iter().enumerate() {
+            let len = operator.spelling.len() as u32;
+            if best.is_some_and(|(_, best_len)| best_len >= len) {
+                continue;
+            }
+            let mut ahead = self.peekable.clone();
+            if operator.spelling.chars().all(|ch| ahead.next() == Some(ch)) {
+                best = Some((Symbol(index as u32), len));
+            }
+        }
+        best
+    }
 
+    fn scan_builtin_token(&mut self) -> Token {
+        let ch = match self.c0() {
+            Some(ch) => ch,
+            None => return Token::Eos,
+        };
+        match ch {
+            '"' | '\'' => self.scan_string(),
+            '<' => {
+                self.advance();
+                match self.c0() {
+                    Some('=') => self.select(Token::Lte),
+                    Some('<') => {
+                        self.advance();
+                        self.select_if('=', Token::AssignShl, Token::Shl)
+                    },
+                    _ => Token::Lt,
+                }
+            },
+            '>' => {
+                self.advance();
+                match self.c0() {
+                    Some('=') => self.select(Token::Gte),
+                    Some('>') => {
+                        self.advance();
+                        match self.c0() {
+                            Some('=') => self.select(Token::AssignSar),
+                            Some('>') => {
+                                self.advance();
+                                self.select_if('=', Token::AssignShr, Token::Shr)
+                            },
+                            _ => Token::Sar
+                        }
+                    },
+                    _ => Token::Gt,
+                }
+            },
+            '=' => {
+                self.advance();
+                self.select_if('=', Token::Eq, Token::Assign)
+            },
+            '!' => {
+                self.advance();
+                self.select_if('=', Token::Ne, Token::Not)
+            },
+            '+' => {
+                self.advance();
+                match self.c0() {
+                    Some('+') => self.select(Token::Inc),
+                    Some('=') => self.select(Token::AssignAdd),
+                    _ => Token::Add,
+                }
+            },
+            '-' => {
+                self.advance();
+                match self.c0() {
+                    Some('-') => self.select(Token::Dec),
+                    Some('=') => self.select(Token::AssignSub),
+                    _ => Token::Sub,
+                }
+            },
+            '*' => {
+                self.advance();
+                self.select_if('=', Token::AssignMul, Token::Mul)
+            },
+            '%' => {
+                self.advance();
+                self.select_if('=', Token::AssignMod, Token::Mod)
+            },
+            '/' => {
+                self.advance();
+                match self.c0() {
+                    Some('/') => self.skip_single_line_comment(),
+                    Some('*') => self.skip_multi_line_comment(),
+                    Some('=') => self.select(Token::AssignDiv),
+                    _ => Token::Div
+                }
+            },
+            '&' => {
+                self.advance();
+                match self.c0() {
+                    Some('&') => self.select(Token::And),
+                    Some('=') => self.select(Token::AssignBitAnd),
+                    _ => Token::BitAnd
+                }
+            },
+            '|' => {
+                self.advance();
+                match self.c0() {
+                    Some('|') => self.select(Token::Or),
+                    Some('=') => self.select(Token::AssignBitOr),
+                    _ => Token::BitOr
                 }
             },
-            None => { println!("None => EOS"); Token::Eos }
+            '^' => {
+                self.advance();
+                self.select_if('=', Token::AssignBitXor, Token::BitXor)
+            },
+            '.' => {
+                self.advance();
+                match self.c0() {
+                    Some(digit) if self.is_decimal_digit(digit) => self.scan_number(),
+                    _ => Token::Period,
+                }
+            },
+            ':' => self.select(Token::Colon),
+            ';' => self.select(Token::Semicolon),
+            ',' => self.select(Token::Comma),
+            '(' => self.select(Token::Lparen),
+            ')' => self.select(Token::Rparen),
+            '[' => self.select(Token::Lbrack),
+            ']' => self.select(Token::Rbrack),
+            '{' => self.select(Token::Lbrace),
+            '}' => self.select(Token::Rbrace),
+            '?' => self.select(Token::Conditional),
+            '@' => self.select(Token::At),
+            '#' => self.scan_private_name(),
+            '~' => self.select(Token::BitNot),
+            _ if self.is_decimal_digit(ch) => self.scan_number(),
+            _ if self.is_identifier_start(ch) => self.scan_identifier(),
+            _ => {
+                self.advance();
+                self.error(format!("unexpected character '{}'", ch.escape_default()));
+                Token::Illegal
+            }
         }
     }
 
     fn skip_single_line_comment(&mut self) -> Token {
-        let mut ch = self.peekable.next();
-        while ch != Some('\n') {
-            ch = self.peekable.next();
+        let begin = self.next.location.begin;
+        while self.c0().is_some_and(|ch| !is_line_terminator(ch)) {
+            self.advance();
         }
-        return Token::Comment(0);
+        Token::Comment(self.position - begin)
     }
 
     fn skip_multi_line_comment(&mut self) -> Token {
-        return Token::Comment(0);
+        let begin = self.next.location.begin;
+        // Consume the '*' of the opening "/*".
+        self.advance();
+        loop {
+            match self.c0() {
+                Some('*') => {
+                    self.advance();
+                    if self.c0() == Some('/') {
+                        self.advance();
+                        return Token::Comment(self.position - begin);
+                    }
+                },
+                Some(_) => self.advance(),
+                None => {
+                    self.error("unterminated comment".to_string());
+                    return Token::Illegal;
+                }
+            }
+        }
     }
 
     fn scan_identifier(&mut self) -> Token {
-        let tok = Token::Identifier("hello".chars().collect());
+        let mut name = String::new();
+        while let Some(ch) = self.c0() {
+            if !self.is_identifier_part(ch) {
+                break;
+            }
+            name.push(ch);
+            self.advance();
+        }
+        self.next.literal.end = self.position;
+        match token::keyword(&name) {
+            Some(keyword) => keyword,
+            None => Token::Identifier(name.chars().collect()),
+        }
+    }
+
+    // A "#!" line is only a shebang at the very start of the file, where
+    // init() skips it; anywhere else '#' must begin a private name.
+    fn scan_private_name(&mut self) -> Token {
         self.advance();
-        tok
+        match self.c0() {
+            Some(ch) if self.is_identifier_start(ch) => {
+                self.next.literal.begin = self.position;
+                match self.scan_identifier() {
+                    Token::Identifier(name) => Token::PrivateName(name),
+                    keyword => Token::PrivateName(keyword.value().chars().collect()),
+                }
+            },
+            Some('!') => {
+                self.advance();
+                self.error("'#!' is only allowed at the start of a file".to_string());
+                Token::Illegal
+            },
+            _ => {
+                self.error("expected a name after '#'".to_string());
+                Token::Illegal
+            }
+        }
     }
 
     fn scan_number(&mut self) -> Token {
-        let tok = Token::Number("0".chars().collect());
-        self.advance();
-        tok
+        // A leading '.' has already been consumed by the caller.
+        let begin = self.next.location.begin;
+        let mut digits: Vec<char> = Vec::new();
+        if self.position > begin {
+            digits.push('.');
+        } else if self.c0() == Some('0') {
+            digits.push('0');
+            self.advance();
+            if let Some(x) = self.c0().filter(|&ch| ch == 'x' || ch == 'X') {
+                digits.push(x);
+                self.advance();
+                let count = self.scan_digits(&mut digits, |ch| ch.is_ascii_hexdigit());
+                if count == 0 {
+                    self.error("missing hexadecimal digits".to_string());
+                    return Token::Illegal;
+                }
+                self.next.literal.end = self.position;
+                return Token::Number(digits);
+            }
+        }
+        self.scan_digits(&mut digits, |ch| ch.is_ascii_digit());
+        if !digits.contains(&'.') && self.c0() == Some('.') {
+            digits.push('.');
+            self.advance();
+            self.scan_digits(&mut digits, |ch| ch.is_ascii_digit());
+        }
+        if let Some(e) = self.c0().filter(|&ch| ch == 'e' || ch == 'E') {
+            digits.push(e);
+            self.advance();
+            if let Some(sign) = self.c0().filter(|&ch| ch == '+' || ch == '-') {
+                digits.push(sign);
+                self.advance();
+            }
+            if self.scan_digits(&mut digits, |ch| ch.is_ascii_digit()) == 0 {
+                self.error("missing exponent digits".to_string());
+                return Token::Illegal;
+            }
+        }
+        self.next.literal.end = self.position;
+        Token::Number(digits)
+    }
+
+    fn scan_digits(&mut self, digits: &mut Vec<char>, accept: fn(char) -> bool) -> usize {
+        let mut count = 0;
+        while let Some(ch) = self.c0().filter(|&ch| accept(ch)) {
+            digits.push(ch);
+            self.advance();
+            count += 1;
+        }
+        count
     }
 
     fn scan_string(&mut self) -> Token {
-        let tok = Token::String("hello".chars().collect());
+        let quote = self.c0().unwrap();
         self.advance();
-        tok
+        self.next.literal.begin = self.position;
+        let mut value = Vec::new();
+        // A bad escape is reported but the literal is still read up to its
+        // closing quote, so the rest of it is not lexed as code.
+        let mut malformed = false;
+        loop {
+            match self.c0() {
+                Some(ch) if ch == quote => {
+                    self.next.literal.end = self.position;
+                    self.advance();
+                    if malformed {
+                        return Token::Illegal;
+                    }
+                    if quote == '\'' && self.config.char_literals {
+                        return self.select_char_literal(value);
+                    }
+                    return Token::String(value);
+                },
+                Some('\\') => {
+                    self.advance();
+                    match self.scan_escape() {
+                        Some(ch) => value.push(ch),
+                        None => malformed = true,
+                    }
+                },
+                Some(ch) if is_line_terminator(ch) => {
+                    self.error("unterminated string literal".to_string());
+                    return Token::Illegal;
+                },
+                None => {
+                    self.error("unterminated string literal".to_string());
+                    return Token::Illegal;
+                },
+                Some(ch) => {
+                    value.push(ch);
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn select_char_literal(&mut self, value: Vec<char>) -> Token {
+        match value[..] {
+            [ch] => Token::CharLiteral(ch),
+            [] => {
+                self.error("empty character literal".to_string());
+                Token::Illegal
+            },
+            _ => {
+                self.error("character literal must contain exactly one character".to_string());
+                Token::Illegal
+            }
+        }
+    }
+
+    // Called with the backslash already consumed.
+    fn scan_escape(&mut self) -> Option<char> {
+        // At the end of input the caller reports the unterminated literal.
+        let ch = self.c0()?;
+        self.advance();
+        let value = match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'v' => '\u{b}',
+            '0' => '\0',
+            'x' => return self.scan_hex_escape(2),
+            'u' => {
+                if self.c0() == Some('{') {
+                    self.advance();
+                    let ch = self.scan_hex_escape(6);
+                    if ch.is_some() && self.c0() == Some('}') {
+                        self.advance();
+                        return ch;
+                    }
+                    self.error("malformed unicode escape".to_string());
+                    return None;
+                }
+                return self.scan_hex_escape(4);
+            },
+            _ => ch,
+        };
+        Some(value)
+    }
+
+    fn scan_hex_escape(&mut self, max: usize) -> Option<char> {
+        let mut value: u32 = 0;
+        let mut count = 0;
+        while count < max {
+            match self.c0().and_then(|ch| ch.to_digit(16)) {
+                Some(digit) => {
+                    value = value * 16 + digit;
+                    self.advance();
+                    count += 1;
+                },
+                None => break,
+            }
+        }
+        // Fixed-width escapes (\xHH, \uHHHH) must be complete; braced
+        // ones only need at least one digit.
+        if count == 0 || (max < 6 && count < max) {
+            self.error("malformed escape sequence".to_string());
+            return None;
+        }
+        let ch = char::from_u32(value);
+        if ch.is_none() {
+            self.error("escape is not a unicode scalar value".to_string());
+        }
+        ch
     }
 
     fn select(&mut self, tok: Token) -> Token {
@@ -252,20 +837,223 @@ impl Scanner<'_> {
     }
 
     fn select_if(&mut self, ch: char, then: Token, el: Token) -> Token {
-        let c0 = self.peekable.next();
-        if ch == c0.unwrap() {
+        if self.c0() == Some(ch) {
             self.advance();
             return then
         }
         el
     }
 
+    fn error(&mut self, message: String) {
+        let location = Span::new(self.next.location.begin, self.position);
+        self.errors.push(ScanError { location, message });
+    }
+
+    fn c0(&mut self) -> Option<char> {
+        self.peekable.peek().copied()
+    }
+
     fn is_decimal_digit(&self, ch: char) -> bool {
-        // FIXME: this is bollocks
-        if ch == '0' {
-            return true;
+        ch.is_ascii_digit()
+    }
+
+    fn is_identifier_start(&self, ch: char) -> bool {
+        ch.is_alphabetic() || ch == '_' || ch == '$'
+    }
+
+    fn is_identifier_part(&self, ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_' || ch == '$'
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(code: &str) -> Vec<Token> {
+        let mut s = Scanner::new(code, 0);
+        s.init();
+        let mut tokens = Vec::new();
+        loop {
+            let token = s.next();
+            if token == Token::Eos {
+                return tokens;
+            }
+            tokens.push(token);
         }
-        false
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn scans_sample() {
+        assert_eq!(
+            tokens("(2 + 2) / 3 >>>= x // done\n"),
+            vec![
+                Token::Lparen,
+                Token::Number(vec!['2']),
+                Token::Add,
+                Token::Number(vec!['2']),
+                Token::Rparen,
+                Token::Div,
+                Token::Number(vec!['3']),
+                Token::AssignShr,
+                Token::Identifier(vec!['x']),
+            ]
+        );
+        assert_eq!(tokens("if s"), vec![Token::If, Token::Identifier(vec!['s'])]);
+    }
+
+    #[test]
+    fn reports_unterminated_string() {
+        let mut s = Scanner::new("'abc", 0);
+        s.init();
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.errors().len(), 1);
+        assert_eq!(s.errors()[0].location, Span::new(0, 4));
+    }
+
+    #[test]
+    fn rewind_restores_state() {
+        let mut s = Scanner::new("a < b > 'c", 0);
+        s.init();
+        assert_eq!(s.next(), Token::Identifier(vec!['a']));
+        let checkpoint = s.checkpoint();
+        assert_eq!(s.next(), Token::Lt);
+        assert_eq!(s.next(), Token::Identifier(vec!['b']));
+        assert_eq!(s.next(), Token::Gt);
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.errors().len(), 1);
+
+        s.rewind(checkpoint);
+        assert_eq!(s.location(), Span::new(0, 1));
+        assert_eq!(s.peek(), Token::Lt);
+        assert_eq!(s.peek_location(), Span::new(2, 3));
+        assert!(s.errors().is_empty());
+        assert_eq!(s.next(), Token::Lt);
+        assert_eq!(s.next(), Token::Identifier(vec!['b']));
+    }
+
+    #[test]
+    fn rewind_scans_saved_tokens_again() {
+        let mut config = Config::default();
+        let pipe = config.add_operator("|>", 4, Associativity::Left).unwrap();
+        let mut s = Scanner::with_config("'a\\tb' |> f\n'c", 10, config);
+        s.init();
+        assert_eq!(s.next(), Token::String(vec!['a', '\t', 'b']));
+        let checkpoint = s.checkpoint();
+        assert_eq!(s.next(), Token::CustomOperator(pipe));
+        assert_eq!(s.next(), Token::Identifier(vec!['f']));
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.line_starts().len(), 2);
+        assert_eq!(s.errors().len(), 1);
+
+        s.rewind(checkpoint);
+        assert_eq!(s.location(), Span::new(10, 16));
+        assert_eq!(s.literal_location(), Span::new(11, 15));
+        assert_eq!(s.peek(), Token::CustomOperator(pipe));
+        assert_eq!(s.peek_location(), Span::new(17, 19));
+        assert_eq!(s.line_starts().len(), 1);
+        assert!(s.errors().is_empty());
+        assert_eq!(s.next(), Token::CustomOperator(pipe));
+        assert_eq!(s.next(), Token::Identifier(vec!['f']));
+        assert_eq!(s.line_column(s.peek_location().begin()), (2, 1));
+    }
+
+    #[test]
+    fn skips_bom_and_shebang() {
+        let code = "\u{feff}#!/usr/bin/env monty\nx";
+        let mut s = Scanner::new(code, 0);
+        s.init();
+        assert!(s.has_bom());
+        assert_eq!(s.shebang(), Some(Span::new(3, 23)));
+        assert_eq!(s.next(), Token::Identifier(vec!['x']));
+        assert_eq!(s.line_column(s.location().begin()), (2, 1));
+    }
+
+    #[test]
+    fn counts_every_line_terminator() {
+        let code = "a\r\nb\rc\u{2028}d /* \n */ e\r\nf";
+        let mut s = Scanner::new(code, 0);
+        s.init();
+        let mut breaks = Vec::new();
+        while s.next() != Token::Eos {
+            breaks.push(s.has_line_terminator_before_next());
+        }
+        assert_eq!(breaks, vec![true, true, true, true, true, false]);
+        assert_eq!(s.line_starts().len(), 6);
+        assert_eq!(s.line_ending(), LineEnding::CrLf);
+        assert_eq!(s.line_column(code.len() as u32 - 1), (6, 1));
+    }
+
+    #[test]
+    fn scans_annotations_and_private_names() {
+        assert_eq!(
+            tokens("@deprecated(\"use foo\") this.#secret #class"),
+            vec![
+                Token::At,
+                Token::Identifier("deprecated".chars().collect()),
+                Token::Lparen,
+                Token::String("use foo".chars().collect()),
+                Token::Rparen,
+                Token::This,
+                Token::Period,
+                Token::PrivateName("secret".chars().collect()),
+                Token::PrivateName("class".chars().collect()),
+            ]
+        );
+        let mut s = Scanner::new("x #!y", 0);
+        s.init();
+        s.next();
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.errors()[0].location, Span::new(2, 4));
+    }
+
+    #[test]
+    fn scans_char_literals_when_enabled() {
+        let config = Config { char_literals: true, ..Default::default() };
+        let mut s = Scanner::with_config("'x' '\\n' '\\u{1f600}' \"y\" '' 'ab'", 0, config);
+        s.init();
+        assert_eq!(s.next(), Token::CharLiteral('x'));
+        assert_eq!(s.next(), Token::CharLiteral('\n'));
+        assert_eq!(s.next(), Token::CharLiteral('\u{1f600}'));
+        assert_eq!(s.next(), Token::String(vec!['y']));
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.next(), Token::Illegal);
+        assert_eq!(s.errors().len(), 2);
+        assert_eq!(s.errors()[1].location, Span::new(28, 32));
+        assert_eq!(tokens("'x'"), vec![Token::String(vec!['x'])]);
+    }
+
+    #[test]
+    fn scans_custom_operators_by_longest_match() {
+        let mut config = Config::default();
+        let pipe = config.add_operator("|>", 4, Associativity::Left).unwrap();
+        let alt = config.add_operator("<|>", 3, Associativity::Right).unwrap();
+        let cmp = config.add_operator("<=>", 10, Associativity::None).unwrap();
+        assert!(config.add_operator(">>>=", 1, Associativity::Left).is_err());
+        assert!(config.add_operator("|>", 1, Associativity::Left).is_err());
+        assert!(config.add_operator("//", 1, Associativity::Left).is_err());
+        assert!(config.add_operator("a+", 1, Associativity::Left).is_err());
+        assert_eq!(config.operator(alt).spelling, "<|>");
+
+        let mut s = Scanner::with_config("a |> b <|> c <=> d <= e || f |>= g", 0, config);
+        s.init();
+        let mut tokens = Vec::new();
+        while s.peek() != Token::Eos {
+            let token = s.next();
+            if !matches!(token, Token::Identifier(_)) {
+                tokens.push(token);
+            }
+        }
+        assert_eq!(tokens, vec![
+            Token::CustomOperator(pipe),
+            Token::CustomOperator(alt),
+            Token::CustomOperator(cmp),
+            Token::Lte,
+            Token::Or,
+            Token::CustomOperator(pipe),
+            Token::Assign,
+        ]);
+        assert_eq!(s.location(), Span::new(33, 34));
+    }
+}
@@ -0,0 +1,326 @@
+use crate::scanner::{self, is_line_terminator, ScanError, Scanner, Span};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+
+    // Byte order marks win; otherwise anything that is valid UTF-8 is
+    // taken as such and the rest is assumed to be Latin-1, which can
+    // decode any byte sequence.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => Encoding::Utf8,
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ if std::str::from_utf8(bytes).is_ok() => Encoding::Utf8,
+            _ => Encoding::Latin1,
+        }
+    }
+
+}
+
+// A run of characters that all take `width` bytes in the decoded text and
+// `original_width` bytes in the input.
+#[derive(Clone, Copy)]
+struct Segment {
+    begin: u32,
+    original_begin: u32,
+    width: u8,
+    original_width: u8,
+}
+
+// Source text transcoded to UTF-8 for the scanner, together with what is
+// needed to translate scanner positions back to offsets in the original
+// bytes. A byte order mark is kept as U+FEFF so the scanner sees it the
+// same way whatever the input encoding was.
+pub struct Source {
+    text: String,
+    encoding: Encoding,
+    original_len: u32,
+    segments: Vec<Segment>,
+}
+
+impl Source {
+
+    pub fn new(bytes: &[u8]) -> Result<Source, ScanError> {
+        Source::decode(bytes, Encoding::detect(bytes))
+    }
+
+    pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<Source, ScanError> {
+        let mut source = Source {
+            text: String::with_capacity(bytes.len()),
+            encoding,
+            original_len: bytes.len() as u32,
+            segments: Vec::new(),
+        };
+        match encoding {
+            Encoding::Utf8 => source.decode_utf8(bytes)?,
+            Encoding::Utf16Le => source.decode_utf16(bytes, u16::from_le_bytes)?,
+            Encoding::Utf16Be => source.decode_utf16(bytes, u16::from_be_bytes)?,
+            Encoding::Latin1 => {
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    source.push(char::from(byte), offset as u32, 1);
+                }
+            }
+        }
+        Ok(source)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn scanner(&self) -> Scanner<'_> {
+        Scanner::new(&self.text, 0)
+    }
+
+    // Byte offset in the original input of a position in text().
+    pub fn original_position(&self, position: u32) -> u32 {
+        if position as usize >= self.text.len() {
+            return self.original_len;
+        }
+        let index = match self.segments.binary_search_by_key(&position, |segment| segment.begin) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let segment = self.segments[index];
+        let chars = (position - segment.begin) / segment.width as u32;
+        segment.original_begin + chars * segment.original_width as u32
+    }
+
+    pub fn original_span(&self, span: Span) -> Span {
+        Span::new(self.original_position(span.begin()), self.original_position(span.end()))
+    }
+
+    fn decode_utf8(&mut self, bytes: &[u8]) -> Result<(), ScanError> {
+        let text = std::str::from_utf8(bytes).map_err(|error| {
+            let begin = error.valid_up_to() as u32;
+            let len = error.error_len().unwrap_or(bytes.len() - begin as usize) as u32;
+            ScanError {
+                location: Span::new(begin, begin + len),
+                message: "invalid UTF-8 sequence".to_string(),
+            }
+        })?;
+        // Offsets are unchanged, so a single segment maps them all.
+        self.text.push_str(text);
+        self.segments.push(Segment { begin: 0, original_begin: 0, width: 1, original_width: 1 });
+        Ok(())
+    }
+
+    fn decode_utf16(&mut self, bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<(), ScanError> {
+        if !bytes.len().is_multiple_of(2) {
+            let end = bytes.len() as u32;
+            return Err(ScanError {
+                location: Span::new(end - 1, end),
+                message: "truncated UTF-16 code unit".to_string(),
+            });
+        }
+        let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+        let mut offset = 0;
+        for decoded in char::decode_utf16(units) {
+            match decoded {
+                Ok(ch) => {
+                    let width = ch.len_utf16() as u32 * 2;
+                    self.push(ch, offset, width as u8);
+                    offset += width;
+                },
+                Err(_) => {
+                    return Err(ScanError {
+                        location: Span::new(offset, offset + 2),
+                        message: "unpaired UTF-16 surrogate".to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, ch: char, original_begin: u32, original_width: u8) {
+        let begin = self.text.len() as u32;
+        let width = ch.len_utf8() as u8;
+        self.text.push(ch);
+        if let Some(last) = self.segments.last() {
+            if last.width == width && last.original_width == original_width {
+                return;
+            }
+        }
+        self.segments.push(Segment { begin, original_begin, width, original_width });
+    }
+
+}
+
+// A named text and where its lines begin, to turn scanner positions into
+// the line and column numbers messages show. Any line terminator the
+// scanner knows ends a line, and "\r\n" counts as one, so the lines are
+// those of Scanner::line_starts. Columns are the scanner's too, in bytes;
+// char_column() is only for lining text up under a printed line.
+pub struct SourceMap {
+    name: String,
+    text: String,
+    lines: Vec<u32>,
+}
+
+impl SourceMap {
+
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> SourceMap {
+        let text = text.into();
+        let mut lines = vec![0];
+        let mut chars = text.char_indices().peekable();
+        while let Some((offset, ch)) = chars.next() {
+            if ch == '\r' && matches!(chars.peek(), Some((_, '\n'))) {
+                continue;
+            }
+            if is_line_terminator(ch) {
+                lines.push((offset + ch.len_utf8()) as u32);
+            }
+        }
+        SourceMap { name: name.into(), text, lines }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.lines.len() as u32
+    }
+
+    // The 1-based line holding `position`.
+    pub fn line(&self, position: u32) -> u32 {
+        match self.lines.binary_search(&position) {
+            Ok(index) => index as u32 + 1,
+            Err(index) => index as u32,
+        }
+    }
+
+    // The 1-based line and column of `position`, as Scanner::line_column
+    // gives them.
+    pub fn line_column(&self, position: u32) -> (u32, u32) {
+        scanner::line_column(&self.lines, position)
+    }
+
+    // The 1-based column of `position` counted in characters, where it
+    // shows on its printed line.
+    pub fn char_column(&self, position: u32) -> u32 {
+        let begin = self.lines[self.line(position) as usize - 1] as usize;
+        let end = (position as usize).min(self.text.len());
+        self.text[begin..end].chars().count() as u32 + 1
+    }
+
+    // The span of a 1-based line, without its terminator.
+    pub fn line_span(&self, line: u32) -> Span {
+        let begin = self.lines[line as usize - 1];
+        let mut end = match self.lines.get(line as usize) {
+            Some(&next) => next,
+            None => self.text.len() as u32,
+        };
+        let text = &self.text[begin as usize..end as usize];
+        if let Some(ch) = text.chars().next_back().filter(|&ch| is_line_terminator(ch)) {
+            end -= ch.len_utf8() as u32;
+            if ch == '\n' && text[..text.len() - 1].ends_with('\r') {
+                end -= 1;
+            }
+        }
+        Span::new(begin, end)
+    }
+
+    pub fn line_text(&self, line: u32) -> &str {
+        let span = self.line_span(line);
+        &self.text[span.begin() as usize..span.end() as usize]
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Token;
+
+    #[test]
+    fn detects_encoding() {
+        assert_eq!(Encoding::detect(b"\xff\xfea\x00"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\xfe\xff\x00a"), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect("caf\u{e9}".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"caf\xe9"), Encoding::Latin1);
+    }
+
+    #[test]
+    fn maps_utf16_positions() {
+        let mut bytes = vec![0xff, 0xfe];
+        for unit in "x = '\u{e9}\u{1f600}' + y".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        let source = Source::new(&bytes).unwrap();
+        assert_eq!(source.encoding(), Encoding::Utf16Le);
+        let mut s = source.scanner();
+        s.init();
+        assert!(s.has_bom());
+        assert_eq!(s.next(), Token::Identifier(vec!['x']));
+        assert_eq!(source.original_span(s.location()), Span::new(2, 4));
+        s.next();
+        assert_eq!(s.next(), Token::String(vec!['\u{e9}', '\u{1f600}']));
+        assert_eq!(source.original_span(s.location()), Span::new(10, 20));
+        s.next();
+        assert_eq!(s.next(), Token::Identifier(vec!['y']));
+        assert_eq!(source.original_span(s.location()), Span::new(26, 28));
+    }
+
+    #[test]
+    fn maps_latin1_positions() {
+        let source = Source::decode(b"caf\xe9 + x", Encoding::Latin1).unwrap();
+        assert_eq!(source.text(), "caf\u{e9} + x");
+        let mut s = source.scanner();
+        s.init();
+        s.next();
+        assert_eq!(source.original_span(s.location()), Span::new(0, 4));
+        s.next();
+        s.next();
+        assert_eq!(source.original_span(s.location()), Span::new(7, 8));
+    }
+
+    #[test]
+    fn rejects_broken_input() {
+        let error = Source::decode(b"\x3d\xd8", Encoding::Utf16Le).err().unwrap();
+        assert_eq!(error.location, Span::new(0, 2));
+        let error = Source::decode(b"ab\xffc", Encoding::Utf8).err().unwrap();
+        assert_eq!(error.location, Span::new(2, 3));
+    }
+
+    #[test]
+    fn maps_lines_and_columns() {
+        let map = SourceMap::new("a.mt", "ab\r\nc\u{e9}d\n\u{2028}x");
+        assert_eq!(map.line_count(), 4);
+        assert_eq!(map.line_column(0), (1, 1));
+        assert_eq!(map.line_column(2), (1, 3));
+        assert_eq!(map.line_column(4), (2, 1));
+        assert_eq!(map.line_column(7), (2, 4));
+        assert_eq!(map.char_column(7), 3);
+        assert_eq!(map.line_column(12), (4, 1));
+        assert_eq!(map.line_text(1), "ab");
+        assert_eq!(map.line_text(2), "c\u{e9}d");
+        assert_eq!(map.line_text(3), "");
+        assert_eq!(map.line_text(4), "x");
+        assert_eq!(map.line_span(2), Span::new(4, 8));
+        // Lines and columns are the scanner's.
+        let mut s = Scanner::new(map.text(), 0);
+        s.init();
+        while s.next() != Token::Eos {
+            assert_eq!(map.line_column(s.location().begin()), s.line_column(s.location().begin()));
+        }
+        assert_eq!(map.line_count() as usize, s.line_starts().len());
+    }
+}
@@ -1,7 +1,13 @@
 use std::fmt;
 
-#[derive(Clone, PartialEq)]
+// Names an operator registered on a scanner Config; the spelling,
+// precedence and associativity live there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Symbol(pub u32);
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum Token {
+    #[default]
     Eos,
     Lparen, // "("
     Rparen, // ")"
@@ -13,6 +19,7 @@ pub enum Token {
     Semicolon, //";"
     Period, // "."
     Conditional, // "?"
+    At, // "@"
     Inc, // "++"
     Dec, // "--"
 
@@ -123,10 +130,17 @@ pub enum Token {
     FalseLiteral, // "false"
     Number(Vec<char>),
     String(Vec<char>),
+    CharLiteral(char),
+
+    /* Operators registered through scanner::Config::add_operator. */
+    CustomOperator(Symbol),
 
     /* Identifiers (not keywords or future reserved words). */
     Identifier(Vec<char>),
 
+    /* Private class members, stored without the leading "#". */
+    PrivateName(Vec<char>), // "#name"
+
     /* Illegal token - not able to scan. */
     Illegal, // "Illegal"
 
@@ -147,6 +161,7 @@ impl Token {
             Token::Semicolon => ";".to_string(),
             Token::Period => ".".to_string(),
             Token::Conditional => "?".to_string(),
+            Token::At => "@".to_string(),
             Token::Inc => "++".to_string(),
             Token::Dec => "--".to_string(),
             Token::Assign => "=".to_string(),
@@ -175,7 +190,7 @@ impl Token {
             Token::Mul => "*".to_string(),
             Token::Div => "/".to_string(),
             Token::Mod => "%".to_string(),
-            Token::Eq => "=".to_string(),
+            Token::Eq => "==".to_string(),
             Token::Ne => "!=".to_string(),
             Token::Lt => "<".to_string(),
             Token::Gt => ">".to_string(),
@@ -208,7 +223,7 @@ impl Token {
             Token::While => "while".to_string(),
             Token::With => "with".to_string(),
             Token::Abstract => "abstract".to_string(),
-            Token::Boolean => " bool".to_string(),
+            Token::Boolean => "bool".to_string(),
             Token::Byte => "byte".to_string(),
             Token::Char => "char".to_string(),
             Token::Class => "class".to_string(),
@@ -239,94 +254,204 @@ impl Token {
             Token::FalseLiteral => "false".to_string(), //
             Token::Number(val) => val.into_iter().collect(),
             Token::String(val) => val.into_iter().collect(),
+            Token::CharLiteral(val) => val.to_string(),
             Token::Identifier(val) => val.into_iter().collect(),
+            Token::PrivateName(val) => std::iter::once('#').chain(val).collect(),
             _ => "".to_string()
         }
     }
 
-    pub fn is_assignment_op(self) -> bool {
+    // Binding power of binary operators, higher binds tighter; zero for
+    // anything that is not one. Assignment and the conditional sit just
+    // above the comma.
+    pub fn precedence(&self) -> u8 {
         match self {
-            Token::Assign => true,
-            Token::AssignBitOr => true,
-            Token::AssignBitXor => true,
-            Token::AssignBitAnd => true,
-            Token::AssignShl => true,
-            Token::AssignSar => true,
-            Token::AssignShr => true,
-            Token::AssignAdd => true,
-            Token::AssignSub => true,
-            Token::AssignMul => true,
-            Token::AssignDiv => true,
-            Token::AssignMod => true,
-            _ => false,
+            Token::Comma => 1,
+            Token::Assign
+            | Token::AssignBitOr
+            | Token::AssignBitXor
+            | Token::AssignBitAnd
+            | Token::AssignShl
+            | Token::AssignSar
+            | Token::AssignShr
+            | Token::AssignAdd
+            | Token::AssignSub
+            | Token::AssignMul
+            | Token::AssignDiv
+            | Token::AssignMod => 2,
+            Token::Conditional => 3,
+            Token::Or => 4,
+            Token::And => 5,
+            Token::BitOr => 6,
+            Token::BitXor => 7,
+            Token::BitAnd => 8,
+            Token::Eq | Token::Ne => 9,
+            Token::Lt | Token::Gt | Token::Lte | Token::Gte | Token::In => 10,
+            Token::Shl | Token::Sar | Token::Shr => 11,
+            Token::Add | Token::Sub => 12,
+            Token::Mul | Token::Div | Token::Mod => 13,
+            _ => 0,
         }
     }
 
-    pub fn is_binary_op(self) -> bool {
-        match self {
-            Token::Comma => true,
-            Token::Or => true,
-            Token::And => true,
-            Token::BitOr => true,
-            Token::BitXor => true,
-            Token::BitAnd => true,
-            Token::Shl => true,
-            Token::Sar => true,
-            Token::Shr => true,
-            Token::Add => true,
-            Token::Sub => true,
-            Token::Mul => true,
-            Token::Div => true,
-            Token::Mod => true,
-            _ => false,
-        }
+    pub fn is_assignment_op(&self) -> bool {
+        matches!(
+            self,
+            Token::Assign
+            | Token::AssignBitOr
+            | Token::AssignBitXor
+            | Token::AssignBitAnd
+            | Token::AssignShl
+            | Token::AssignSar
+            | Token::AssignShr
+            | Token::AssignAdd
+            | Token::AssignSub
+            | Token::AssignMul
+            | Token::AssignDiv
+            | Token::AssignMod
+        )
     }
 
-    pub fn is_compare_op(self) -> bool {
-        match self {
-            Token::Eq => true,
-            Token::Ne => true,
-            Token::Lt => true,
-            Token::Gt => true,
-            Token::Lte => true,
-            Token::Gte => true,
-            Token::In => true,
-            _ => false,
-        }
+    pub fn is_binary_op(&self) -> bool {
+        matches!(
+            self,
+            Token::Comma
+            | Token::Or
+            | Token::And
+            | Token::BitOr
+            | Token::BitXor
+            | Token::BitAnd
+            | Token::Shl
+            | Token::Sar
+            | Token::Shr
+            | Token::Add
+            | Token::Sub
+            | Token::Mul
+            | Token::Div
+            | Token::Mod
+        )
     }
 
-    pub fn is_bit_op(self) -> bool {
-        match self {
-            Token::BitOr => true,
-            Token::BitXor => true,
-            Token::BitAnd => true,
-            Token::Shl => true,
-            Token::Sar => true,
-            Token::Shr => true,
-            Token::BitNot => true,
-            _ => false,
-        }
+    pub fn is_compare_op(&self) -> bool {
+        matches!(
+            self,
+            Token::Eq
+            | Token::Ne
+            | Token::Lt
+            | Token::Gt
+            | Token::Lte
+            | Token::Gte
+            | Token::In
+        )
     }
 
-    pub fn is_unary_op(self) -> bool {
-        match self {
-            Token::Not => true,
-            Token::BitNot => true,
-            Token::Delete => true,
-            Token::Typeof => true,
-            Token::Void => true,
-            Token::Add => true,
-            Token::Sub => true,
-            _ => false,
-        }
+    pub fn is_bit_op(&self) -> bool {
+        matches!(
+            self,
+            Token::BitOr
+            | Token::BitXor
+            | Token::BitAnd
+            | Token::Shl
+            | Token::Sar
+            | Token::Shr
+            | Token::BitNot
+        )
     }
 
-    pub fn is_count_op(self) -> bool {
-        match self {
-            Token::Inc => true,
-            Token::Dec => true,
-            _ => false,
-        }
+    pub fn is_unary_op(&self) -> bool {
+        matches!(
+            self,
+            Token::Not
+            | Token::BitNot
+            | Token::Delete
+            | Token::Typeof
+            | Token::Void
+            | Token::Add
+            | Token::Sub
+        )
+    }
+
+    pub fn is_count_op(&self) -> bool {
+        matches!(
+            self,
+            Token::Inc
+            | Token::Dec
+        )
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Token::In
+            | Token::Delete
+            | Token::Typeof
+            | Token::Void
+            | Token::Break
+            | Token::Case
+            | Token::Catch
+            | Token::Continue
+            | Token::Default
+            | Token::Do
+            | Token::Else
+            | Token::Finally
+            | Token::For
+            | Token::Function
+            | Token::If
+            | Token::New
+            | Token::Return
+            | Token::Switch
+            | Token::This
+            | Token::Throw
+            | Token::Try
+            | Token::Var
+            | Token::While
+            | Token::With
+        )
+    }
+
+    pub fn is_reserved_word(&self) -> bool {
+        matches!(
+            self,
+            Token::Abstract
+            | Token::Boolean
+            | Token::Byte
+            | Token::Char
+            | Token::Class
+            | Token::Const
+            | Token::Double
+            | Token::Enum
+            | Token::Export
+            | Token::Extends
+            | Token::Final
+            | Token::Float
+            | Token::Goto
+            | Token::Implements
+            | Token::Import
+            | Token::Int
+            | Token::Interface
+            | Token::Long
+            | Token::Native
+            | Token::Package
+            | Token::Private
+            | Token::Protected
+            | Token::Public
+            | Token::Short
+            | Token::Static
+            | Token::Super
+            | Token::Throws
+        )
+    }
+
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            Token::NullLiteral
+            | Token::TrueLiteral
+            | Token::FalseLiteral
+            | Token::Number(_)
+            | Token::String(_)
+            | Token::CharLiteral(_)
+        )
     }
 
 }
@@ -345,6 +470,7 @@ impl fmt::Display for Token {
             Token::Semicolon => "Semicolon",
             Token::Period => "Period",
             Token::Conditional => "Conditional",
+            Token::At => "At",
             Token::Inc => "Inc",
             Token::Dec => "Dec",
             Token::Assign => "Assign",
@@ -406,7 +532,7 @@ impl fmt::Display for Token {
             Token::While => "While",
             Token::With => "With",
             Token::Abstract => "Abstract",
-            Token::Boolean => " Boolean",
+            Token::Boolean => "Boolean",
             Token::Byte => "Byte",
             Token::Char => "Char",
             Token::Class => "Class",
@@ -437,7 +563,10 @@ impl fmt::Display for Token {
             Token::FalseLiteral => "False",
             Token::Number(_) => "Number",
             Token::String(_) => "String",
+            Token::CharLiteral(_) => "CharLiteral",
+            Token::CustomOperator(_) => "CustomOperator",
             Token::Identifier(_) => "Identifier",
+            Token::PrivateName(_) => "PrivateName",
             Token::Illegal => "Illegal",
             Token::Comment(_) => "Comment",
         };
@@ -445,6 +574,170 @@ impl fmt::Display for Token {
     }
 }
 
-impl Default for Token {
-    fn default() -> Self { Token::Eos }
-}
\ No newline at end of file
+// Every token that carries no payload, in declaration order.
+pub const FIXED_TOKENS: [Token; 103] = [
+    Token::Eos,
+    Token::Lparen,
+    Token::Rparen,
+    Token::Lbrack,
+    Token::Rbrack,
+    Token::Lbrace,
+    Token::Rbrace,
+    Token::Colon,
+    Token::Semicolon,
+    Token::Period,
+    Token::Conditional,
+    Token::At,
+    Token::Inc,
+    Token::Dec,
+    Token::Assign,
+    Token::AssignBitOr,
+    Token::AssignBitXor,
+    Token::AssignBitAnd,
+    Token::AssignShl,
+    Token::AssignSar,
+    Token::AssignShr,
+    Token::AssignAdd,
+    Token::AssignSub,
+    Token::AssignMul,
+    Token::AssignDiv,
+    Token::AssignMod,
+    Token::Comma,
+    Token::Or,
+    Token::And,
+    Token::BitOr,
+    Token::BitXor,
+    Token::BitAnd,
+    Token::Shl,
+    Token::Sar,
+    Token::Shr,
+    Token::Add,
+    Token::Sub,
+    Token::Mul,
+    Token::Div,
+    Token::Mod,
+    Token::Eq,
+    Token::Ne,
+    Token::Lt,
+    Token::Gt,
+    Token::Lte,
+    Token::Gte,
+    Token::In,
+    Token::Not,
+    Token::BitNot,
+    Token::Delete,
+    Token::Typeof,
+    Token::Void,
+    Token::Break,
+    Token::Case,
+    Token::Catch,
+    Token::Continue,
+    Token::Default,
+    Token::Do,
+    Token::Else,
+    Token::Finally,
+    Token::For,
+    Token::Function,
+    Token::If,
+    Token::New,
+    Token::Return,
+    Token::Switch,
+    Token::This,
+    Token::Throw,
+    Token::Try,
+    Token::Var,
+    Token::While,
+    Token::With,
+    Token::Abstract,
+    Token::Boolean,
+    Token::Byte,
+    Token::Char,
+    Token::Class,
+    Token::Const,
+    Token::Double,
+    Token::Enum,
+    Token::Export,
+    Token::Extends,
+    Token::Final,
+    Token::Float,
+    Token::Goto,
+    Token::Implements,
+    Token::Import,
+    Token::Int,
+    Token::Interface,
+    Token::Long,
+    Token::Native,
+    Token::Package,
+    Token::Private,
+    Token::Protected,
+    Token::Public,
+    Token::Short,
+    Token::Static,
+    Token::Super,
+    Token::Throws,
+    Token::NullLiteral,
+    Token::TrueLiteral,
+    Token::FalseLiteral,
+    Token::Illegal,
+];
+
+pub fn keyword(name: &str) -> Option<Token> {
+    let token = match name {
+        "in" => Token::In,
+        "delete" => Token::Delete,
+        "typeof" => Token::Typeof,
+        "void" => Token::Void,
+        "break" => Token::Break,
+        "case" => Token::Case,
+        "catch" => Token::Catch,
+        "continue" => Token::Continue,
+        "default" => Token::Default,
+        "do" => Token::Do,
+        "else" => Token::Else,
+        "finally" => Token::Finally,
+        "for" => Token::For,
+        "def" => Token::Function,
+        "if" => Token::If,
+        "new" => Token::New,
+        "return" => Token::Return,
+        "switch" => Token::Switch,
+        "this" => Token::This,
+        "throw" => Token::Throw,
+        "try" => Token::Try,
+        "var" => Token::Var,
+        "while" => Token::While,
+        "with" => Token::With,
+        "abstract" => Token::Abstract,
+        "bool" => Token::Boolean,
+        "byte" => Token::Byte,
+        "char" => Token::Char,
+        "class" => Token::Class,
+        "const" => Token::Const,
+        "double" => Token::Double,
+        "enum" => Token::Enum,
+        "export" => Token::Export,
+        "extends" => Token::Extends,
+        "final" => Token::Final,
+        "float" => Token::Float,
+        "goto" => Token::Goto,
+        "implements" => Token::Implements,
+        "import" => Token::Import,
+        "int" => Token::Int,
+        "interface" => Token::Interface,
+        "long" => Token::Long,
+        "native" => Token::Native,
+        "package" => Token::Package,
+        "private" => Token::Private,
+        "protected" => Token::Protected,
+        "public" => Token::Public,
+        "short" => Token::Short,
+        "static" => Token::Static,
+        "super" => Token::Super,
+        "throws" => Token::Throws,
+        "null" => Token::NullLiteral,
+        "true" => Token::TrueLiteral,
+        "false" => Token::FalseLiteral,
+        _ => return None,
+    };
+    Some(token)
+}
@@ -0,0 +1,124 @@
+use lex::dump;
+use lex::parallel;
+use lex::scanner::{Config, Scanner};
+use lex::token::{self, Token, FIXED_TOKENS};
+use proptest::prelude::*;
+
+fn lex(code: &str, config: Config) -> Vec<Token> {
+    let mut s = Scanner::with_config(code, 0, config);
+    s.init();
+    let mut tokens = Vec::new();
+    loop {
+        let token = s.next();
+        if token == Token::Eos {
+            return tokens;
+        }
+        tokens.push(token);
+    }
+}
+
+// Token::value() gives string and character literals without their
+// quotes, so those are quoted and escaped here to make them lexable.
+fn print(token: &Token) -> String {
+    match token {
+        Token::String(_) => quote(&token.clone().value(), '"'),
+        Token::CharLiteral(_) => quote(&token.clone().value(), '\''),
+        _ => token.clone().value(),
+    }
+}
+
+fn quote(value: &str, quote: char) -> String {
+    let mut out = String::new();
+    out.push(quote);
+    for ch in value.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            ch if ch == quote => {
+                out.push('\\');
+                out.push(ch);
+            },
+            ch => out.push(ch),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+fn fixed_token() -> impl Strategy<Value = Token> {
+    let tokens: Vec<Token> = FIXED_TOKENS
+        .iter()
+        .filter(|token| !matches!(token, Token::Eos | Token::Illegal))
+        .cloned()
+        .collect();
+    proptest::sample::select(tokens)
+}
+
+fn name() -> impl Strategy<Value = Vec<char>> {
+    "[a-zA-Z_$][a-zA-Z0-9_$]{0,8}"
+        .prop_filter("keywords are not names", |name| token::keyword(name).is_none())
+        .prop_map(|name| name.chars().collect())
+}
+
+fn token() -> impl Strategy<Value = Token> {
+    prop_oneof![
+        4 => fixed_token(),
+        2 => name().prop_map(Token::Identifier),
+        1 => "#[a-zA-Z_][a-zA-Z0-9_]{0,8}".prop_map(|name| Token::PrivateName(name[1..].chars().collect())),
+        1 => "[0-9]{1,6}(\\.[0-9]{1,3})?([eE][+-]?[0-9]{1,2})?".prop_map(|number| Token::Number(number.chars().collect())),
+        1 => "0[xX][0-9a-fA-F]{1,8}".prop_map(|number| Token::Number(number.chars().collect())),
+        1 => any::<String>().prop_map(|value| Token::String(value.chars().collect())),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn printed_tokens_lex_back(tokens in prop::collection::vec(token(), 0..32)) {
+        let code = tokens.iter().map(print).collect::<Vec<_>>().join(" ");
+        prop_assert_eq!(lex(&code, Config::default()), tokens);
+    }
+
+    #[test]
+    fn printed_char_literals_lex_back(values in prop::collection::vec(any::<char>(), 0..16)) {
+        let tokens: Vec<Token> = values.into_iter().map(Token::CharLiteral).collect();
+        let code = tokens.iter().map(print).collect::<Vec<_>>().join(" ");
+        let config = Config { char_literals: true, ..Default::default() };
+        prop_assert_eq!(lex(&code, config), tokens);
+    }
+
+    #[test]
+    fn parallel_lexing_matches_sequential(
+        code in "[a-z0-9 '\"/*\\\\\n\r\u{2028}#.=]{0,200}",
+        chunks in 1usize..12,
+    ) {
+        let config = Config::default();
+        prop_assert_eq!(parallel::records(&code, &config, chunks), dump::records(&code, config));
+    }
+
+    #[test]
+    fn arbitrary_input_is_lossless(code in any::<String>()) {
+        let records = dump::records(&code, Config::default());
+        let mut end = 0;
+        let mut text = String::new();
+        for record in &records {
+            prop_assert!(record.location.begin() >= end);
+            prop_assert!(record.location.end() as usize <= code.len());
+            prop_assert!(record.token == Token::Eos || !record.location.is_empty());
+            end = record.location.end();
+            text.push_str(&record.trivia);
+            text.push_str(&record.text);
+        }
+        prop_assert_eq!(text, code);
+    }
+}
+
+#[test]
+fn short_inputs_do_not_panic() {
+    for code in ["", ".", "<", ">", ">>", "0x", "1e", "'", "\"\\", "/*", "#", "\r", "\u{feff}", "#!"] {
+        dump::records(code, Config::default());
+        dump::records(code, Config { char_literals: true, comments: true, ..Default::default() });
+    }
+}
@@ -1,6 +1,35 @@
-use lex::scanner::Scanner;
+use std::env;
+use std::fs;
+use std::process;
+use lex::cache::TokenCache;
+use lex::dump;
+use lex::scanner::{Config, Scanner};
 
 fn main() {
+    // `main [--cache DIR] FILE` dumps the tokens of FILE as JSON Lines,
+    // reusing tokens cached in DIR when FILE has not changed.
+    let mut args = env::args().skip(1);
+    let mut cache = None;
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cache" => cache = args.next().map(TokenCache::new),
+            _ => file = Some(arg),
+        }
+    }
+    if let Some(path) = file {
+        let code = fs::read_to_string(&path).unwrap_or_else(|error| {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        });
+        let config = Config::default();
+        let records = match cache {
+            Some(cache) => cache.records(&code, &config),
+            None => dump::records(&code, config),
+        };
+        print!("{}", dump::to_json_lines(&records));
+        return;
+    }
     //let code = "string s = 'hello world';\nif s.lenght() > 4 {\n print('hello');\n} else {\n print('ola');\n}\n";
     let code = "(2 + 2) / 3 = ?\n";
     let mut s = Scanner::new(code, 0);
@@ -0,0 +1,570 @@
+// Abstract syntax tree for monty programs.
+//
+// Every node carries the Span of the source text it was parsed from and a
+// NodeId that is unique within one parse, so tools can attach information
+// to nodes in side tables. Operators are kept as the lex Token that spelled
+// them, so the Token predicates (is_binary_op() and friends) apply to the
+// tree as well.
+
+use lex::scanner::Span;
+use lex::token::Token;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+// Hands out node ids in the order nodes are created.
+#[derive(Clone, Debug, Default)]
+pub struct NodeIds {
+    next: u32,
+}
+
+impl NodeIds {
+
+    pub fn new() -> NodeIds {
+        NodeIds { next: 0 }
+    }
+
+    pub fn fresh(&mut self) -> NodeId {
+        let id = NodeId(self.next);
+        self.next += 1;
+        id
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identifier {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: String,
+}
+
+/* Modules. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    pub id: NodeId,
+    pub span: Span,
+    pub body: Vec<Stmt>,
+}
+
+/* Statements. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: StmtKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    Block(Block),
+    Expr(Expr),
+    Empty,
+    If {
+        condition: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
+    },
+    For {
+        init: Option<ForInit>,
+        condition: Option<Expr>,
+        update: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    ForIn {
+        target: ForInit,
+        object: Expr,
+        body: Box<Stmt>,
+    },
+    Switch {
+        discriminant: Expr,
+        cases: Vec<SwitchCase>,
+    },
+    Break(Option<Identifier>),
+    Continue(Option<Identifier>),
+    Return(Option<Expr>),
+    Throw(Expr),
+    Try {
+        block: Block,
+        catch: Option<CatchClause>,
+        finally: Option<Block>,
+    },
+    With {
+        object: Expr,
+        body: Box<Stmt>,
+    },
+    Labelled {
+        label: Identifier,
+        body: Box<Stmt>,
+    },
+    Decl(Decl),
+    // What was skipped after a syntax error.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub span: Span,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ForInit {
+    Var(VarDecl),
+    Expr(Expr),
+    // The assignment target heading a for-in loop.
+    Pattern(Pattern),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwitchCase {
+    pub id: NodeId,
+    pub span: Span,
+    // None for the `default:` case.
+    pub test: Option<Expr>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatchClause {
+    pub id: NodeId,
+    pub span: Span,
+    pub param: Option<Identifier>,
+    pub body: Block,
+}
+
+/* Declarations. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decl {
+    pub id: NodeId,
+    pub span: Span,
+    pub annotations: Vec<Annotation>,
+    pub modifiers: Vec<Modifier>,
+    pub kind: DeclKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeclKind {
+    Var(VarDecl),
+    Function(Function),
+    Class(Class),
+    Interface(Interface),
+    Enum(Enum),
+    Package(Path),
+    Import(Import),
+    Export(Export),
+}
+
+// `@name` or `@name(arguments)` in front of a declaration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Path,
+    pub arguments: Option<Vec<Expr>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    Public,
+    Protected,
+    Private,
+    Static,
+    Abstract,
+    Final,
+    Native,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarKind {
+    Var,
+    Const,
+    Final,
+    // Introduced by a type, as in `int x = 1;`.
+    Typed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VarDecl {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: VarKind,
+    pub ty: Option<Type>,
+    pub declarators: Vec<Declarator>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declarator {
+    pub id: NodeId,
+    pub span: Span,
+    pub pattern: Pattern,
+    pub init: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub id: NodeId,
+    pub span: Span,
+    // None for anonymous function expressions, arrow functions and
+    // methods, whose name is on the ClassMember.
+    pub name: Option<Identifier>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub throws: Vec<Type>,
+    pub body: FunctionBody,
+    pub arrow: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FunctionBody {
+    Block(Block),
+    // The expression body of an arrow function.
+    Expr(Box<Expr>),
+    // Interface methods, abstract and native methods.
+    None,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub id: NodeId,
+    pub span: Span,
+    pub pattern: Pattern,
+    pub ty: Option<Type>,
+    pub default: Option<Expr>,
+    // `...name` collects the remaining arguments.
+    pub rest: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Class {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Identifier,
+    pub extends: Option<Type>,
+    pub implements: Vec<Type>,
+    pub members: Vec<ClassMember>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassMember {
+    pub id: NodeId,
+    pub span: Span,
+    pub annotations: Vec<Annotation>,
+    pub modifiers: Vec<Modifier>,
+    pub kind: ClassMemberKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClassMemberKind {
+    Field {
+        name: MemberName,
+        ty: Option<Type>,
+        init: Option<Expr>,
+    },
+    Method {
+        name: MemberName,
+        function: Function,
+    },
+    Constructor(Function),
+    // What was skipped after a syntax error.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MemberName {
+    Public(Identifier),
+    // `#name`, stored without the '#'.
+    Private(Identifier),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interface {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Identifier,
+    pub extends: Vec<Type>,
+    // Method signatures; their bodies are FunctionBody::None.
+    pub methods: Vec<Function>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enum {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Identifier,
+    pub variants: Vec<EnumVariant>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumVariant {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Identifier,
+    pub value: Option<Expr>,
+}
+
+// A dotted name such as `a.b.C`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub id: NodeId,
+    pub span: Span,
+    pub segments: Vec<Identifier>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Import {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ImportKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportKind {
+    // `import a.b.C;`
+    Path(Path),
+    // `import { x, y as z } from "path";`
+    Named {
+        names: Vec<ImportName>,
+        from: StringLiteral,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportName {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Identifier,
+    pub alias: Option<Identifier>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Export {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExportKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportKind {
+    // `export def f() {}`, `export class C {}`, ...
+    Decl(Box<Decl>),
+    // `export { x, y as z };` and, with a source, `export { x } from "path";`
+    Named {
+        names: Vec<ImportName>,
+        from: Option<StringLiteral>,
+    },
+    // `export * from "path";`
+    All(StringLiteral),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringLiteral {
+    pub id: NodeId,
+    pub span: Span,
+    pub value: String,
+}
+
+/* Types. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Type {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: TypeKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeKind {
+    Primitive(Primitive),
+    Named(Path),
+    // `List<int>`
+    Generic {
+        base: Path,
+        arguments: Vec<Type>,
+    },
+    Array(Box<Type>),
+    // `T?`, which also admits null.
+    Nullable(Box<Type>),
+    // `(int, string) => bool`
+    Function {
+        params: Vec<Type>,
+        result: Box<Type>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    Boolean,
+    Byte,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Void,
+}
+
+impl Primitive {
+
+    pub fn from_token(token: &Token) -> Option<Primitive> {
+        match token {
+            Token::Boolean => Some(Primitive::Boolean),
+            Token::Byte => Some(Primitive::Byte),
+            Token::Char => Some(Primitive::Char),
+            Token::Short => Some(Primitive::Short),
+            Token::Int => Some(Primitive::Int),
+            Token::Long => Some(Primitive::Long),
+            Token::Float => Some(Primitive::Float),
+            Token::Double => Some(Primitive::Double),
+            Token::Void => Some(Primitive::Void),
+            _ => None,
+        }
+    }
+
+}
+
+/* Expressions. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    Number(String),
+    String(String),
+    Char(char),
+    Bool(bool),
+    Null,
+    This,
+    Super,
+    Identifier(Identifier),
+    Array(Vec<Expr>),
+    // `...xs` in an array literal.
+    Spread(Box<Expr>),
+    Object(Vec<Property>),
+    Function(Box<Function>),
+    // Operators are the Token that spelled them.
+    Unary {
+        op: Token,
+        operand: Box<Expr>,
+    },
+    // `++` and `--`.
+    Update {
+        op: Token,
+        prefix: bool,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: Token,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    // Compound assignments only target names, members and elements.
+    Assign {
+        op: Token,
+        target: Box<Pattern>,
+        value: Box<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    Sequence(Vec<Expr>),
+    // A parenthesized expression, kept so the tree still covers the
+    // parentheses.
+    Paren(Box<Expr>),
+    Member {
+        object: Box<Expr>,
+        property: MemberName,
+    },
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+    },
+    // `new C` has no argument list, `new C()` an empty one.
+    New {
+        callee: Box<Expr>,
+        arguments: Option<Vec<Expr>>,
+    },
+    // Stands in for a missing expression; its span is empty.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Property {
+    pub id: NodeId,
+    pub span: Span,
+    pub key: PropertyKey,
+    pub value: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyKey {
+    Identifier(Identifier),
+    String(StringLiteral),
+    Number(String),
+}
+
+/* Patterns. */
+
+// What a declarator or parameter binds, or what an assignment assigns to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: PatternKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatternKind {
+    Identifier(Identifier),
+    // `[a, b = 1, ...rest]`
+    Array(Vec<PatternElement>),
+    // `{a, b: c = 1}`
+    Object(Vec<PatternProperty>),
+    // A member, element or parenthesized target. Only assignments have
+    // these.
+    Expr(Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternElement {
+    pub id: NodeId,
+    pub span: Span,
+    pub pattern: Pattern,
+    pub default: Option<Expr>,
+    // `...rest` takes the remaining elements.
+    pub rest: bool,
+}
+
+// `{a}` is short for `{a: a}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternProperty {
+    pub id: NodeId,
+    pub span: Span,
+    pub key: PropertyKey,
+    pub value: Pattern,
+    pub default: Option<Expr>,
+}
@@ -0,0 +1,1333 @@
+// Lossless concrete syntax tree. Every byte of the source is in a leaf:
+// tokens, comments and the whitespace between them, so printing a tree
+// gives back the text it was built from.
+//
+// As in a green/red tree, the green tree is immutable, shared and knows
+// only kinds, widths and text; it is built from the AST, with each AST node
+// becoming a green node holding the tokens within its span. The red tree
+// (SyntaxNode, SyntaxToken) is a cheaply cloned cursor over it adding parent
+// pointers and absolute positions. Editing a node builds a new root that
+// shares everything outside the path to it. Typed views (AstNode) give
+// named access to the children of some kinds of node.
+
+use std::fmt;
+use std::rc::Rc;
+use lex::scanner::{Config, Scanner, Span};
+use lex::token::Token;
+use crate::ast::*;
+use crate::parser::{parse_module_recovering, ParseError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    // Leaves.
+    Whitespace,
+    Comment,
+    Token,
+    // Nodes.
+    Module,
+    Block,
+    ExprStmt,
+    EmptyStmt,
+    IfStmt,
+    WhileStmt,
+    DoWhileStmt,
+    ForStmt,
+    ForInStmt,
+    SwitchStmt,
+    SwitchCase,
+    BreakStmt,
+    ContinueStmt,
+    ReturnStmt,
+    ThrowStmt,
+    TryStmt,
+    CatchClause,
+    WithStmt,
+    LabelledStmt,
+    DeclStmt,
+    ErrorStmt,
+    Decl,
+    Annotation,
+    VarDecl,
+    Declarator,
+    Function,
+    Param,
+    Class,
+    ClassMember,
+    Interface,
+    Enum,
+    EnumVariant,
+    Package,
+    Import,
+    ImportName,
+    Export,
+    Path,
+    StringLiteral,
+    Name,
+    Type,
+    LiteralExpr,
+    ThisExpr,
+    SuperExpr,
+    NameExpr,
+    ArrayExpr,
+    SpreadExpr,
+    ObjectExpr,
+    Property,
+    FunctionExpr,
+    UnaryExpr,
+    UpdateExpr,
+    BinaryExpr,
+    AssignExpr,
+    ConditionalExpr,
+    SequenceExpr,
+    ParenExpr,
+    MemberExpr,
+    IndexExpr,
+    CallExpr,
+    NewExpr,
+    ErrorExpr,
+    NamePattern,
+    ArrayPattern,
+    ObjectPattern,
+    PatternElement,
+    PatternProperty,
+    ExprPattern,
+}
+
+impl SyntaxKind {
+
+    pub fn is_trivia(self) -> bool {
+        matches!(self, SyntaxKind::Whitespace | SyntaxKind::Comment)
+    }
+
+}
+
+/* Green tree. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    // None for whitespace and a shebang line.
+    token: Option<Token>,
+    text: String,
+}
+
+impl GreenToken {
+
+    // A leaf for `text`, which should scan as a single token, comment or
+    // run of whitespace.
+    pub fn new(text: &str) -> GreenToken {
+        let config = Config { comments: true, ..Default::default() };
+        let mut scanner = Scanner::with_config(text, 0, config);
+        scanner.init();
+        let (kind, token) = match scanner.next() {
+            Token::Eos => (SyntaxKind::Whitespace, None),
+            token @ Token::Comment(_) => (SyntaxKind::Comment, Some(token)),
+            token => (SyntaxKind::Token, Some(token)),
+        };
+        GreenToken { kind, token, text: text.to_string() }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn token(&self) -> Option<&Token> {
+        self.token.as_ref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+
+    pub fn width(&self) -> u32 {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len() as u32,
+        }
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    width: u32,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> GreenNode {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode { kind, width, children }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    // Copies of this node with one child changed. The other children are
+    // shared, not copied.
+    pub fn replace_child(&self, index: usize, element: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = element;
+        GreenNode::new(self.kind, children)
+    }
+
+    pub fn insert_child(&self, index: usize, element: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children.insert(index, element);
+        GreenNode::new(self.kind, children)
+    }
+
+    pub fn remove_child(&self, index: usize) -> GreenNode {
+        let mut children = self.children.clone();
+        children.remove(index);
+        GreenNode::new(self.kind, children)
+    }
+
+}
+
+impl fmt::Display for GreenNode {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => f.write_str(&token.text)?,
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/* Red tree. */
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    // Position among the parent's children.
+    index: usize,
+    offset: u32,
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    parent: SyntaxNode,
+    index: usize,
+    offset: u32,
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData { green, parent: None, index: 0, offset: 0 }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.width)
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    // This node, its parent, and so on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = Vec::with_capacity(self.0.green.children.len());
+        for (index, child) in self.0.green.children.iter().enumerate() {
+            elements.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    parent: Some(self.clone()),
+                    index,
+                    offset,
+                }))),
+                GreenElement::Token(_) => SyntaxElement::Token(SyntaxToken { parent: self.clone(), index, offset }),
+            });
+            offset += child.width();
+        }
+        elements
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn child(&self, kind: SyntaxKind) -> Option<SyntaxNode> {
+        self.children().into_iter().find(|child| child.kind() == kind)
+    }
+
+    // This node and all nodes below it, parents before children.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    // The leaves below this node, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    // The tokens below this node other than trivia.
+    pub fn significant_tokens(&self) -> Vec<SyntaxToken> {
+        self.tokens().into_iter().filter(|token| !token.kind().is_trivia()).collect()
+    }
+
+    // The leaf holding the byte at `position`.
+    pub fn token_at(&self, position: u32) -> Option<SyntaxToken> {
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) if node.span().begin() <= position && position < node.span().end() => {
+                    return node.token_at(position);
+                },
+                SyntaxElement::Token(token) if token.span().begin() <= position && position < token.span().end() => {
+                    return Some(token);
+                },
+                _ => {},
+            }
+        }
+        None
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    // A new tree, returned as its root, with `replacement` in place of this
+    // node. This tree is left as it was.
+    pub fn replace_with(&self, replacement: GreenNode) -> SyntaxNode {
+        self.replace_element(GreenElement::Node(Rc::new(replacement)))
+    }
+
+    fn replace_element(&self, element: GreenElement) -> SyntaxNode {
+        match (&self.0.parent, element) {
+            (Some(parent), element) => {
+                let green = parent.0.green.replace_child(self.0.index, element);
+                parent.replace_with(green)
+            },
+            (None, GreenElement::Node(green)) => SyntaxNode::new_root(green),
+            (None, GreenElement::Token(_)) => unreachable!("the root is a node"),
+        }
+    }
+
+}
+
+impl PartialEq for SyntaxNode {
+
+    // The same node of the same tree.
+    fn eq(&self, other: &SyntaxNode) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+
+}
+
+impl fmt::Display for SyntaxNode {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+
+}
+
+impl fmt::Debug for SyntaxNode {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}@{}..{}", self.kind(), self.span().begin(), self.span().end())
+    }
+
+}
+
+impl SyntaxToken {
+
+    pub fn green(&self) -> &GreenToken {
+        match &self.parent.0.green.children[self.index] {
+            GreenElement::Token(token) => token,
+            GreenElement::Node(_) => unreachable!("tokens point at leaves"),
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.green().kind
+    }
+
+    pub fn token(&self) -> Option<&Token> {
+        self.green().token()
+    }
+
+    pub fn text(&self) -> &str {
+        self.green().text()
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green().text.len() as u32)
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    // A new tree, returned as its root, with `replacement` in place of this
+    // token.
+    pub fn replace_with(&self, replacement: GreenToken) -> SyntaxNode {
+        let green = self.parent.0.green.replace_child(self.index, GreenElement::Token(Rc::new(replacement)));
+        self.parent.replace_with(green)
+    }
+
+}
+
+impl fmt::Debug for SyntaxToken {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), self.span().begin(), self.span().end(), self.text())
+    }
+
+}
+
+/* Typed views. */
+
+// A SyntaxNode of a known kind, with accessors named after its parts.
+pub trait AstNode: Sized {
+
+    fn cast(node: SyntaxNode) -> Option<Self>;
+
+    fn syntax(&self) -> &SyntaxNode;
+
+}
+
+fn cast_kind(node: SyntaxNode, kind: SyntaxKind) -> Option<SyntaxNode> {
+    match node.kind() == kind {
+        true => Some(node),
+        false => None,
+    }
+}
+
+fn nth_child(node: &SyntaxNode, n: usize) -> Option<SyntaxNode> {
+    node.children().into_iter().nth(n)
+}
+
+pub struct Name(SyntaxNode);
+
+impl AstNode for Name {
+
+    fn cast(node: SyntaxNode) -> Option<Name> {
+        cast_kind(node, SyntaxKind::Name).map(Name)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+}
+
+impl Name {
+
+    pub fn token(&self) -> Option<SyntaxToken> {
+        self.0.significant_tokens().into_iter().next()
+    }
+
+}
+
+pub struct IfStmt(SyntaxNode);
+
+impl AstNode for IfStmt {
+
+    fn cast(node: SyntaxNode) -> Option<IfStmt> {
+        cast_kind(node, SyntaxKind::IfStmt).map(IfStmt)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+}
+
+impl IfStmt {
+
+    pub fn condition(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 0)
+    }
+
+    pub fn then_branch(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 1)
+    }
+
+    pub fn else_branch(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 2)
+    }
+
+}
+
+pub struct FunctionDef(SyntaxNode);
+
+impl AstNode for FunctionDef {
+
+    fn cast(node: SyntaxNode) -> Option<FunctionDef> {
+        cast_kind(node, SyntaxKind::Function).map(FunctionDef)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+}
+
+impl FunctionDef {
+
+    pub fn name(&self) -> Option<Name> {
+        self.0.child(SyntaxKind::Name).and_then(Name::cast)
+    }
+
+    pub fn params(&self) -> Vec<SyntaxNode> {
+        self.0.children().into_iter().filter(|child| child.kind() == SyntaxKind::Param).collect()
+    }
+
+    pub fn body(&self) -> Option<SyntaxNode> {
+        let children = self.0.children();
+        children.last().filter(|child| !matches!(child.kind(), SyntaxKind::Name | SyntaxKind::Param | SyntaxKind::Type)).cloned()
+    }
+
+}
+
+pub struct CallExpr(SyntaxNode);
+
+impl AstNode for CallExpr {
+
+    fn cast(node: SyntaxNode) -> Option<CallExpr> {
+        cast_kind(node, SyntaxKind::CallExpr).map(CallExpr)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+}
+
+impl CallExpr {
+
+    pub fn callee(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 0)
+    }
+
+    pub fn arguments(&self) -> Vec<SyntaxNode> {
+        self.0.children().into_iter().skip(1).collect()
+    }
+
+}
+
+pub struct BinaryExpr(SyntaxNode);
+
+impl AstNode for BinaryExpr {
+
+    fn cast(node: SyntaxNode) -> Option<BinaryExpr> {
+        cast_kind(node, SyntaxKind::BinaryExpr).map(BinaryExpr)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+}
+
+impl BinaryExpr {
+
+    pub fn left(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 0)
+    }
+
+    pub fn right(&self) -> Option<SyntaxNode> {
+        nth_child(&self.0, 1)
+    }
+
+    // The operator is the one token directly in the node.
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        self.0.children_with_tokens().into_iter().find_map(|element| match element {
+            SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+}
+
+/* Building. */
+
+// Parses `code` into a lossless tree, recovering from syntax errors like
+// parse_module_recovering().
+pub fn parse_tree(code: &str) -> (SyntaxNode, Vec<ParseError>) {
+    let (module, errors) = parse_module_recovering(code);
+    (SyntaxNode::new_root(Rc::new(build(code, &module))), errors)
+}
+
+// The green tree of `code`, given its AST.
+pub fn build(code: &str, module: &Module) -> GreenNode {
+    Builder::root(code, SyntaxKind::Module, |builder| {
+        for stmt in &module.body {
+            builder.stmt(stmt);
+        }
+    })
+}
+
+// The green tree of `code`, which is all one block, given its AST.
+pub(crate) fn build_block(code: &str, block: &crate::ast::Block) -> GreenNode {
+    Builder::root(code, SyntaxKind::Block, |builder| {
+        for stmt in &block.body {
+            builder.stmt(stmt);
+        }
+    })
+}
+
+// The tokens and comments of `code`, with the gaps between them as
+// whitespace, covering all of it.
+fn leaves(code: &str) -> Vec<(Span, SyntaxKind, Option<Token>)> {
+    let config = Config { comments: true, ..Default::default() };
+    let mut scanner = Scanner::with_config(code, 0, config);
+    scanner.init();
+    let mut leaves = Vec::new();
+    if let Some(shebang) = scanner.shebang() {
+        leaves.push((shebang, SyntaxKind::Comment, None));
+    }
+    loop {
+        let token = scanner.next();
+        let span = scanner.location();
+        let kind = match token {
+            Token::Eos => break,
+            Token::Comment(_) => SyntaxKind::Comment,
+            _ => SyntaxKind::Token,
+        };
+        leaves.push((span, kind, Some(token)));
+    }
+    let mut end = 0;
+    let mut covered = Vec::with_capacity(leaves.len() * 2);
+    for leaf in leaves {
+        if leaf.0.begin() > end {
+            covered.push((Span::new(end, leaf.0.begin()), SyntaxKind::Whitespace, None));
+        }
+        end = leaf.0.end();
+        covered.push(leaf);
+    }
+    if (end as usize) < code.len() {
+        covered.push((Span::new(end, code.len() as u32), SyntaxKind::Whitespace, None));
+    }
+    covered
+}
+
+// Nests the leaves under nodes in the order the AST is walked, which is
+// source order. A node takes the leaves within its span not taken by its
+// children; the leaves between nodes go to their parent.
+struct Builder<'a> {
+    code: &'a str,
+    leaves: Vec<(Span, SyntaxKind, Option<Token>)>,
+    next: usize,
+    stack: Vec<(SyntaxKind, Vec<GreenElement>)>,
+}
+
+impl<'a> Builder<'a> {
+
+    // A node of `kind` covering all of `code`, with the nodes `children`
+    // adds.
+    fn root(code: &'a str, kind: SyntaxKind, children: impl FnOnce(&mut Self)) -> GreenNode {
+        let mut builder = Builder { code, leaves: leaves(code), next: 0, stack: vec![(kind, Vec::new())] };
+        children(&mut builder);
+        builder.take(|_| true);
+        let (kind, children) = builder.stack.pop().unwrap();
+        GreenNode::new(kind, children)
+    }
+
+    fn start(&mut self, kind: SyntaxKind, begin: u32) {
+        self.take(|span| span.begin() < begin);
+        self.stack.push((kind, Vec::new()));
+    }
+
+    fn finish(&mut self, end: u32) {
+        self.take(|span| span.end() <= end);
+        self.split(end);
+        let (kind, children) = self.stack.pop().unwrap();
+        let node = GreenElement::Node(Rc::new(GreenNode::new(kind, children)));
+        self.stack.last_mut().unwrap().1.push(node);
+    }
+
+    // A node can end within a token the parser split, as the `>>` closing
+    // two generic types. The token is split here too, so that each node
+    // keeps its own part.
+    fn split(&mut self, at: u32) {
+        let span = match self.leaves.get(self.next) {
+            Some(&(span, SyntaxKind::Token, _)) if span.begin() < at && at < span.end() => span,
+            _ => return,
+        };
+        let scan = |span: Span| {
+            let mut scanner = Scanner::new(&self.code[span.begin() as usize..span.end() as usize], 0);
+            scanner.init();
+            scanner.next()
+        };
+        let (head, tail) = (Span::new(span.begin(), at), Span::new(at, span.end()));
+        let leaves = [(head, SyntaxKind::Token, Some(scan(head))), (tail, SyntaxKind::Token, Some(scan(tail)))];
+        self.leaves.splice(self.next..self.next + 1, leaves);
+        self.take(|span| span.end() <= at);
+    }
+
+    // Moves leaves into the innermost open node while `wanted` says so.
+    fn take(&mut self, wanted: impl Fn(Span) -> bool) {
+        while let Some((span, kind, token)) = self.leaves.get(self.next) {
+            if !wanted(*span) {
+                return;
+            }
+            let text = self.code[span.begin() as usize..span.end() as usize].to_string();
+            let leaf = GreenToken { kind: *kind, token: token.clone(), text };
+            self.stack.last_mut().unwrap().1.push(GreenElement::Token(Rc::new(leaf)));
+            self.next += 1;
+        }
+    }
+
+    fn node(&mut self, kind: SyntaxKind, span: Span, children: impl FnOnce(&mut Self)) {
+        self.start(kind, span.begin());
+        children(self);
+        self.finish(span.end());
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let kind = match &stmt.kind {
+            StmtKind::Block(_) => SyntaxKind::Block,
+            StmtKind::Expr(_) => SyntaxKind::ExprStmt,
+            StmtKind::Empty => SyntaxKind::EmptyStmt,
+            StmtKind::If { .. } => SyntaxKind::IfStmt,
+            StmtKind::While { .. } => SyntaxKind::WhileStmt,
+            StmtKind::DoWhile { .. } => SyntaxKind::DoWhileStmt,
+            StmtKind::For { .. } => SyntaxKind::ForStmt,
+            StmtKind::ForIn { .. } => SyntaxKind::ForInStmt,
+            StmtKind::Switch { .. } => SyntaxKind::SwitchStmt,
+            StmtKind::Break(_) => SyntaxKind::BreakStmt,
+            StmtKind::Continue(_) => SyntaxKind::ContinueStmt,
+            StmtKind::Return(_) => SyntaxKind::ReturnStmt,
+            StmtKind::Throw(_) => SyntaxKind::ThrowStmt,
+            StmtKind::Try { .. } => SyntaxKind::TryStmt,
+            StmtKind::With { .. } => SyntaxKind::WithStmt,
+            StmtKind::Labelled { .. } => SyntaxKind::LabelledStmt,
+            StmtKind::Decl(_) => SyntaxKind::DeclStmt,
+            StmtKind::Error => SyntaxKind::ErrorStmt,
+        };
+        // A block statement is just the block.
+        if let StmtKind::Block(block) = &stmt.kind {
+            return self.block(block);
+        }
+        self.node(kind, stmt.span, |builder| match &stmt.kind {
+            StmtKind::Expr(expr) | StmtKind::Throw(expr) => builder.expr(expr),
+            StmtKind::If { condition, then, otherwise } => {
+                builder.expr(condition);
+                builder.stmt(then);
+                if let Some(otherwise) = otherwise {
+                    builder.stmt(otherwise);
+                }
+            },
+            StmtKind::While { condition, body } => {
+                builder.expr(condition);
+                builder.stmt(body);
+            },
+            StmtKind::DoWhile { body, condition } => {
+                builder.stmt(body);
+                builder.expr(condition);
+            },
+            StmtKind::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    builder.for_init(init);
+                }
+                for expr in condition.iter().chain(update) {
+                    builder.expr(expr);
+                }
+                builder.stmt(body);
+            },
+            StmtKind::ForIn { target, object, body } => {
+                builder.for_init(target);
+                builder.expr(object);
+                builder.stmt(body);
+            },
+            StmtKind::Switch { discriminant, cases } => {
+                builder.expr(discriminant);
+                for case in cases {
+                    builder.node(SyntaxKind::SwitchCase, case.span, |builder| {
+                        if let Some(test) = &case.test {
+                            builder.expr(test);
+                        }
+                        for stmt in &case.body {
+                            builder.stmt(stmt);
+                        }
+                    });
+                }
+            },
+            StmtKind::Break(label) | StmtKind::Continue(label) => {
+                if let Some(label) = label {
+                    builder.name(label);
+                }
+            },
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    builder.expr(value);
+                }
+            },
+            StmtKind::Try { block, catch, finally } => {
+                builder.block(block);
+                if let Some(catch) = catch {
+                    builder.node(SyntaxKind::CatchClause, catch.span, |builder| {
+                        if let Some(param) = &catch.param {
+                            builder.name(param);
+                        }
+                        builder.block(&catch.body);
+                    });
+                }
+                if let Some(finally) = finally {
+                    builder.block(finally);
+                }
+            },
+            StmtKind::With { object, body } => {
+                builder.expr(object);
+                builder.stmt(body);
+            },
+            StmtKind::Labelled { label, body } => {
+                builder.name(label);
+                builder.stmt(body);
+            },
+            StmtKind::Decl(decl) => builder.decl(decl),
+            StmtKind::Block(_) | StmtKind::Empty | StmtKind::Error => {},
+        });
+    }
+
+    fn block(&mut self, block: &crate::ast::Block) {
+        self.node(SyntaxKind::Block, block.span, |builder| {
+            for stmt in &block.body {
+                builder.stmt(stmt);
+            }
+        });
+    }
+
+    fn for_init(&mut self, init: &ForInit) {
+        match init {
+            ForInit::Var(decl) => self.var_decl(decl),
+            ForInit::Expr(expr) => self.expr(expr),
+            ForInit::Pattern(pattern) => self.pattern(pattern),
+        }
+    }
+
+    fn decl(&mut self, decl: &crate::ast::Decl) {
+        self.node(SyntaxKind::Decl, decl.span, |builder| {
+            for annotation in &decl.annotations {
+                builder.node(SyntaxKind::Annotation, annotation.span, |builder| {
+                    builder.path(&annotation.name);
+                    for argument in annotation.arguments.iter().flatten() {
+                        builder.expr(argument);
+                    }
+                });
+            }
+            match &decl.kind {
+                DeclKind::Var(decl) => builder.var_decl(decl),
+                DeclKind::Function(function) => builder.function(function),
+                DeclKind::Class(class) => builder.class(class),
+                DeclKind::Interface(interface) => {
+                    builder.node(SyntaxKind::Interface, interface.span, |builder| {
+                        builder.name(&interface.name);
+                        for ty in &interface.extends {
+                            builder.ty(ty);
+                        }
+                        for method in &interface.methods {
+                            builder.function(method);
+                        }
+                    });
+                },
+                DeclKind::Enum(enumeration) => {
+                    builder.node(SyntaxKind::Enum, enumeration.span, |builder| {
+                        builder.name(&enumeration.name);
+                        for variant in &enumeration.variants {
+                            builder.node(SyntaxKind::EnumVariant, variant.span, |builder| {
+                                builder.name(&variant.name);
+                                if let Some(value) = &variant.value {
+                                    builder.expr(value);
+                                }
+                            });
+                        }
+                    });
+                },
+                DeclKind::Package(path) => {
+                    builder.node(SyntaxKind::Package, decl.span, |builder| builder.path(path));
+                },
+                DeclKind::Import(import) => {
+                    builder.node(SyntaxKind::Import, import.span, |builder| match &import.kind {
+                        ImportKind::Path(path) => builder.path(path),
+                        ImportKind::Named { names, from } => {
+                            builder.import_names(names);
+                            builder.string_literal(from);
+                        },
+                    });
+                },
+                DeclKind::Export(export) => {
+                    builder.node(SyntaxKind::Export, export.span, |builder| match &export.kind {
+                        ExportKind::Decl(decl) => builder.decl(decl),
+                        ExportKind::Named { names, from } => {
+                            builder.import_names(names);
+                            if let Some(from) = from {
+                                builder.string_literal(from);
+                            }
+                        },
+                        ExportKind::All(from) => builder.string_literal(from),
+                    });
+                },
+            }
+        });
+    }
+
+    fn var_decl(&mut self, decl: &crate::ast::VarDecl) {
+        self.node(SyntaxKind::VarDecl, decl.span, |builder| {
+            if let Some(ty) = &decl.ty {
+                builder.ty(ty);
+            }
+            for declarator in &decl.declarators {
+                builder.node(SyntaxKind::Declarator, declarator.span, |builder| {
+                    builder.pattern(&declarator.pattern);
+                    if let Some(init) = &declarator.init {
+                        builder.expr(init);
+                    }
+                });
+            }
+        });
+    }
+
+    fn function(&mut self, function: &crate::ast::Function) {
+        self.node(SyntaxKind::Function, function.span, |builder| {
+            if let Some(name) = &function.name {
+                builder.name(name);
+            }
+            for param in &function.params {
+                builder.node(SyntaxKind::Param, param.span, |builder| {
+                    if let Some(ty) = &param.ty {
+                        builder.ty(ty);
+                    }
+                    builder.pattern(&param.pattern);
+                    if let Some(default) = &param.default {
+                        builder.expr(default);
+                    }
+                });
+            }
+            for ty in function.return_type.iter().chain(&function.throws) {
+                builder.ty(ty);
+            }
+            match &function.body {
+                FunctionBody::Block(block) => builder.block(block),
+                FunctionBody::Expr(expr) => builder.expr(expr),
+                FunctionBody::None => {},
+            }
+        });
+    }
+
+    fn class(&mut self, class: &crate::ast::Class) {
+        self.node(SyntaxKind::Class, class.span, |builder| {
+            builder.name(&class.name);
+            for ty in class.extends.iter().chain(&class.implements) {
+                builder.ty(ty);
+            }
+            for member in &class.members {
+                builder.node(SyntaxKind::ClassMember, member.span, |builder| {
+                    for annotation in &member.annotations {
+                        builder.node(SyntaxKind::Annotation, annotation.span, |builder| {
+                            builder.path(&annotation.name);
+                            for argument in annotation.arguments.iter().flatten() {
+                                builder.expr(argument);
+                            }
+                        });
+                    }
+                    match &member.kind {
+                        ClassMemberKind::Field { name, ty, init } => {
+                            if let Some(ty) = ty {
+                                builder.ty(ty);
+                            }
+                            builder.member_name(name);
+                            if let Some(init) = init {
+                                builder.expr(init);
+                            }
+                        },
+                        ClassMemberKind::Method { name, function } => {
+                            builder.member_name(name);
+                            builder.function(function);
+                        },
+                        ClassMemberKind::Constructor(function) => builder.function(function),
+                        ClassMemberKind::Error => {},
+                    }
+                });
+            }
+        });
+    }
+
+    fn import_names(&mut self, names: &[ImportName]) {
+        for name in names {
+            self.node(SyntaxKind::ImportName, name.span, |builder| {
+                builder.name(&name.name);
+                if let Some(alias) = &name.alias {
+                    builder.name(alias);
+                }
+            });
+        }
+    }
+
+    fn ty(&mut self, ty: &crate::ast::Type) {
+        self.node(SyntaxKind::Type, ty.span, |builder| match &ty.kind {
+            TypeKind::Primitive(_) => {},
+            TypeKind::Named(path) => builder.path(path),
+            TypeKind::Generic { base, arguments } => {
+                builder.path(base);
+                for argument in arguments {
+                    builder.ty(argument);
+                }
+            },
+            TypeKind::Array(inner) | TypeKind::Nullable(inner) => builder.ty(inner),
+            TypeKind::Function { params, result } => {
+                for param in params {
+                    builder.ty(param);
+                }
+                builder.ty(result);
+            },
+        });
+    }
+
+    fn path(&mut self, path: &crate::ast::Path) {
+        self.node(SyntaxKind::Path, path.span, |builder| {
+            for segment in &path.segments {
+                builder.name(segment);
+            }
+        });
+    }
+
+    fn name(&mut self, name: &Identifier) {
+        self.node(SyntaxKind::Name, name.span, |_| {});
+    }
+
+    fn member_name(&mut self, name: &MemberName) {
+        match name {
+            MemberName::Public(name) | MemberName::Private(name) => self.name(name),
+        }
+    }
+
+    fn string_literal(&mut self, literal: &crate::ast::StringLiteral) {
+        self.node(SyntaxKind::StringLiteral, literal.span, |_| {});
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let kind = match &expr.kind {
+            ExprKind::Number(_) | ExprKind::String(_) | ExprKind::Char(_) | ExprKind::Bool(_) | ExprKind::Null => {
+                SyntaxKind::LiteralExpr
+            },
+            ExprKind::This => SyntaxKind::ThisExpr,
+            ExprKind::Super => SyntaxKind::SuperExpr,
+            ExprKind::Identifier(_) => SyntaxKind::NameExpr,
+            ExprKind::Array(_) => SyntaxKind::ArrayExpr,
+            ExprKind::Spread(_) => SyntaxKind::SpreadExpr,
+            ExprKind::Object(_) => SyntaxKind::ObjectExpr,
+            ExprKind::Function(_) => SyntaxKind::FunctionExpr,
+            ExprKind::Unary { .. } => SyntaxKind::UnaryExpr,
+            ExprKind::Update { .. } => SyntaxKind::UpdateExpr,
+            ExprKind::Binary { .. } => SyntaxKind::BinaryExpr,
+            ExprKind::Assign { .. } => SyntaxKind::AssignExpr,
+            ExprKind::Conditional { .. } => SyntaxKind::ConditionalExpr,
+            ExprKind::Sequence(_) => SyntaxKind::SequenceExpr,
+            ExprKind::Paren(_) => SyntaxKind::ParenExpr,
+            ExprKind::Member { .. } => SyntaxKind::MemberExpr,
+            ExprKind::Index { .. } => SyntaxKind::IndexExpr,
+            ExprKind::Call { .. } => SyntaxKind::CallExpr,
+            ExprKind::New { .. } => SyntaxKind::NewExpr,
+            ExprKind::Error => SyntaxKind::ErrorExpr,
+        };
+        self.node(kind, expr.span, |builder| match &expr.kind {
+            ExprKind::Identifier(name) => builder.name(name),
+            ExprKind::Array(items) | ExprKind::Sequence(items) => {
+                for item in items {
+                    builder.expr(item);
+                }
+            },
+            ExprKind::Object(properties) => {
+                for property in properties {
+                    builder.node(SyntaxKind::Property, property.span, |builder| {
+                        builder.property_key(&property.key);
+                        builder.expr(&property.value);
+                    });
+                }
+            },
+            ExprKind::Function(function) => builder.function(function),
+            ExprKind::Spread(operand)
+            | ExprKind::Unary { operand, .. }
+            | ExprKind::Update { operand, .. }
+            | ExprKind::Paren(operand) => builder.expr(operand),
+            ExprKind::Binary { left, right, .. } => {
+                builder.expr(left);
+                builder.expr(right);
+            },
+            ExprKind::Assign { target, value, .. } => {
+                builder.pattern(target);
+                builder.expr(value);
+            },
+            ExprKind::Conditional { condition, then, otherwise } => {
+                builder.expr(condition);
+                builder.expr(then);
+                builder.expr(otherwise);
+            },
+            ExprKind::Member { object, property } => {
+                builder.expr(object);
+                builder.member_name(property);
+            },
+            ExprKind::Index { object, index } => {
+                builder.expr(object);
+                builder.expr(index);
+            },
+            ExprKind::Call { callee, arguments } => {
+                builder.expr(callee);
+                for argument in arguments {
+                    builder.expr(argument);
+                }
+            },
+            ExprKind::New { callee, arguments } => {
+                builder.expr(callee);
+                for argument in arguments.iter().flatten() {
+                    builder.expr(argument);
+                }
+            },
+            _ => {},
+        });
+    }
+
+    fn property_key(&mut self, key: &PropertyKey) {
+        match key {
+            PropertyKey::Identifier(name) => self.name(name),
+            PropertyKey::String(literal) => self.string_literal(literal),
+            PropertyKey::Number(_) => {},
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        let kind = match &pattern.kind {
+            PatternKind::Identifier(_) => SyntaxKind::NamePattern,
+            PatternKind::Array(_) => SyntaxKind::ArrayPattern,
+            PatternKind::Object(_) => SyntaxKind::ObjectPattern,
+            PatternKind::Expr(_) => SyntaxKind::ExprPattern,
+        };
+        self.node(kind, pattern.span, |builder| match &pattern.kind {
+            PatternKind::Identifier(name) => builder.name(name),
+            PatternKind::Array(elements) => {
+                for element in elements {
+                    builder.node(SyntaxKind::PatternElement, element.span, |builder| {
+                        builder.pattern(&element.pattern);
+                        if let Some(default) = &element.default {
+                            builder.expr(default);
+                        }
+                    });
+                }
+            },
+            PatternKind::Object(properties) => {
+                for property in properties {
+                    builder.node(SyntaxKind::PatternProperty, property.span, |builder| {
+                        builder.property_key(&property.key);
+                        builder.pattern(&property.value);
+                        if let Some(default) = &property.default {
+                            builder.expr(default);
+                        }
+                    });
+                }
+            },
+            PatternKind::Expr(expr) => builder.expr(expr),
+        });
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(code: &str) -> SyntaxNode {
+        let (root, errors) = parse_tree(code);
+        assert!(errors.is_empty(), "{:?}", errors);
+        root
+    }
+
+    fn find(root: &SyntaxNode, kind: SyntaxKind) -> SyntaxNode {
+        root.descendants().into_iter().find(|node| node.kind() == kind).unwrap()
+    }
+
+    #[test]
+    fn prints_back_the_source() {
+        let sources = [
+            "",
+            "  \n",
+            "#!/usr/bin/env monty\n// hello\nx = 1 /* inline */ + 2;\n",
+            "def f(int a, [b, c] = d): List<Map<int, int>> { return a >> 1 }",
+            "var {a, b: [c]} = o; ({x} = y)\n",
+            "@Entity(1) public class C extends B { #x = 1; def m() {} C() {} }",
+            "import { x as y } from 'z'\nexport * from \"w\";",
+            "switch (x) { case 1: break; default: }  ",
+        ];
+        for code in sources {
+            let root = tree(code);
+            assert_eq!(root.text(), code);
+            assert_eq!(root.span(), Span::new(0, code.len() as u32));
+        }
+        // The `>>` closing both generics is split between them.
+        let root = tree(sources[3]);
+        let types: Vec<String> = root.descendants().into_iter()
+            .filter(|node| node.kind() == SyntaxKind::Type)
+            .map(|node| node.text())
+            .collect();
+        assert_eq!(types, ["int", "List<Map<int, int>>", "Map<int, int>", "int", "int"]);
+
+        let code = "x = (a;\nif (a {}\nb = 1 c = 2";
+        let (root, errors) = parse_tree(code);
+        assert!(!errors.is_empty());
+        assert_eq!(root.text(), code);
+    }
+
+    #[test]
+    fn keeps_trivia_as_leaves() {
+        let root = tree("#!x\na /* b */ ;// c");
+        let tokens = root.tokens();
+        let leaves: Vec<(SyntaxKind, &str)> = tokens.iter().map(|token| (token.kind(), token.text())).collect();
+        assert_eq!(leaves, [
+            (SyntaxKind::Comment, "#!x"),
+            (SyntaxKind::Whitespace, "\n"),
+            (SyntaxKind::Token, "a"),
+            (SyntaxKind::Whitespace, " "),
+            (SyntaxKind::Comment, "/* b */"),
+            (SyntaxKind::Whitespace, " "),
+            (SyntaxKind::Token, ";"),
+            (SyntaxKind::Comment, "// c"),
+        ]);
+        assert_eq!(tokens[2].token(), Some(&Token::Identifier(vec!['a'])));
+        assert_eq!(tokens[2].parent().kind(), SyntaxKind::Name);
+    }
+
+    #[test]
+    fn links_nodes_to_their_parents() {
+        let root = tree("if (a) { f(b + c) }");
+        let token = root.token_at(15).unwrap();
+        assert_eq!(token.text(), "c");
+        assert_eq!(token.span(), Span::new(15, 16));
+        let kinds: Vec<SyntaxKind> = token.parent().ancestors().map(|node| node.kind()).collect();
+        assert_eq!(kinds, [
+            SyntaxKind::Name,
+            SyntaxKind::NameExpr,
+            SyntaxKind::BinaryExpr,
+            SyntaxKind::CallExpr,
+            SyntaxKind::ExprStmt,
+            SyntaxKind::Block,
+            SyntaxKind::IfStmt,
+            SyntaxKind::Module,
+        ]);
+        let binary = find(&root, SyntaxKind::BinaryExpr);
+        assert_eq!(binary.span(), Span::new(11, 16));
+        assert_eq!(binary.text(), "b + c");
+        assert!(binary.ancestors().nth(4).unwrap() == find(&root, SyntaxKind::IfStmt));
+        assert!(root.parent().is_none());
+    }
+
+    #[test]
+    fn gives_typed_views() {
+        let root = tree("def f(a, b) { if (a) g(a * b, 1); else {} }");
+        let function = FunctionDef::cast(find(&root, SyntaxKind::Function)).unwrap();
+        assert_eq!(function.name().unwrap().token().unwrap().text(), "f");
+        assert_eq!(function.params().len(), 2);
+        assert_eq!(function.body().unwrap().kind(), SyntaxKind::Block);
+        let branch = IfStmt::cast(find(&root, SyntaxKind::IfStmt)).unwrap();
+        assert_eq!(branch.condition().unwrap().text(), "a");
+        assert_eq!(branch.then_branch().unwrap().text(), "g(a * b, 1);");
+        assert_eq!(branch.else_branch().unwrap().text(), "{}");
+        let call = CallExpr::cast(find(&root, SyntaxKind::CallExpr)).unwrap();
+        assert_eq!(call.callee().unwrap().text(), "g");
+        assert_eq!(call.arguments().len(), 2);
+        let binary = BinaryExpr::cast(call.arguments()[0].clone()).unwrap();
+        assert_eq!(binary.operator().unwrap().text(), "*");
+        assert_eq!(binary.right().unwrap().text(), "b");
+        assert!(IfStmt::cast(root).is_none());
+    }
+
+    #[test]
+    fn edits_without_touching_the_original() {
+        let code = "def f(x) {\n  // twice\n  return x  *  2\n}\n";
+        let root = tree(code);
+        let name = FunctionDef::cast(find(&root, SyntaxKind::Function)).unwrap().name().unwrap();
+        let renamed = name.token().unwrap().replace_with(GreenToken::new("double"));
+        assert_eq!(renamed.text(), "def double(x) {\n  // twice\n  return x  *  2\n}\n");
+        assert_eq!(root.text(), code);
+
+        let binary = find(&renamed, SyntaxKind::BinaryExpr);
+        let operand = GreenNode::new(SyntaxKind::NameExpr, vec![
+            GreenElement::Node(Rc::new(GreenNode::new(SyntaxKind::Name, vec![
+                GreenElement::Token(Rc::new(GreenToken::new("factor"))),
+            ]))),
+        ]);
+        let right = BinaryExpr::cast(binary).unwrap().right().unwrap();
+        let edited = right.replace_with(operand);
+        assert_eq!(edited.text(), "def double(x) {\n  // twice\n  return x  *  factor\n}\n");
+        assert_eq!(find(&edited, SyntaxKind::BinaryExpr).span(), Span::new(36, 48));
+        // Untouched subtrees are shared with the tree the edit started from.
+        let before = find(&renamed, SyntaxKind::Function).children();
+        let after = find(&edited, SyntaxKind::Function).children();
+        assert_eq!(after.iter().map(SyntaxNode::kind).collect::<Vec<_>>(), [SyntaxKind::Name, SyntaxKind::Param, SyntaxKind::Block]);
+        assert!(Rc::ptr_eq(before[1].green(), after[1].green()));
+        assert!(!Rc::ptr_eq(before[2].green(), after[2].green()));
+    }
+
+    #[test]
+    fn classifies_new_tokens() {
+        assert_eq!(GreenToken::new("  ").kind(), SyntaxKind::Whitespace);
+        assert_eq!(GreenToken::new("/* c */").kind(), SyntaxKind::Comment);
+        assert_eq!(GreenToken::new("while").token(), Some(&Token::While));
+    }
+}
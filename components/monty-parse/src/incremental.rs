@@ -0,0 +1,195 @@
+// Incremental reparsing of a concrete syntax tree after a text edit.
+//
+// Only the innermost block around the edit is parsed again, and its new
+// tree put in place of the old one; everything else is shared with the old
+// tree. A block parses the same on its own as within the module, as long as
+// the braces that open and close it are still its own: what is around it
+// only sees a balanced `{ ... }`. When the edit breaks that, as by adding a
+// '}' or opening a comment, the next enclosing block is tried, and in the
+// end the whole text parsed again.
+
+use lex::diagnostic::Edit;
+use lex::scanner::{Scanner, Span};
+use lex::token::Token;
+use crate::cst::{build_block, parse_tree, GreenElement, SyntaxKind, SyntaxNode};
+use crate::parser::{ParseError, Parser};
+
+// The tree and errors of the text of `root` after `edit`, given the errors
+// found in it before. The result is the same as parse_tree() would give.
+pub fn reparse(root: &SyntaxNode, errors: &[ParseError], edit: &Edit) -> (SyntaxNode, Vec<ParseError>) {
+    for block in enclosing_blocks(root, edit.span) {
+        if let Some(reparsed) = reparse_block(&block, errors, edit) {
+            return reparsed;
+        }
+    }
+    let mut code = root.text();
+    code.replace_range(edit.span.begin() as usize..edit.span.end() as usize, &edit.replacement);
+    parse_tree(&code)
+}
+
+// The blocks whose braces are outside `span`, innermost first.
+fn enclosing_blocks(root: &SyntaxNode, span: Span) -> Vec<SyntaxNode> {
+    let mut blocks = Vec::new();
+    let mut node = root.clone();
+    loop {
+        let child = node.children().into_iter().find(|child| {
+            child.span().begin() < span.begin() && span.end() < child.span().end()
+        });
+        match child {
+            Some(child) => {
+                if child.kind() == SyntaxKind::Block {
+                    blocks.push(child.clone());
+                }
+                node = child;
+            },
+            None => break,
+        }
+    }
+    blocks.reverse();
+    blocks
+}
+
+fn reparse_block(block: &SyntaxNode, errors: &[ParseError], edit: &Edit) -> Option<(SyntaxNode, Vec<ParseError>)> {
+    let old = block.span();
+    let mut code = block.text();
+    let (begin, end) = (edit.span.begin() - old.begin(), edit.span.end() - old.begin());
+    code.replace_range(begin as usize..end as usize, &edit.replacement);
+    let mut parser = Parser::new(Scanner::new(&code, 0));
+    let parsed = parser.block().ok()?;
+    parser.expect_end().ok()?;
+    // The block must still end with its own '}', not one closing a block
+    // within it or swallowed by a string or comment.
+    let green = build_block(&code, &parsed);
+    match green.children().last() {
+        Some(GreenElement::Token(token)) if token.token() == Some(&Token::Rbrace) => {},
+        _ => return None,
+    }
+    if parsed.span != Span::new(0, code.len() as u32) {
+        return None;
+    }
+
+    // Errors are found in source order. Those within the old block give
+    // way to the new ones, and those after it move with the text.
+    let delta = code.len() as i64 - i64::from(old.end() - old.begin());
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    for error in errors {
+        let positions = positions(error);
+        if positions.iter().all(|&position| position <= old.begin()) {
+            before.push(error.clone());
+        } else if positions.iter().all(|&position| position >= old.end()) {
+            after.push(shift(error, |position| (i64::from(position) + delta) as u32));
+        } else if !positions.iter().all(|&position| old.begin() < position && position <= old.end()) {
+            return None;
+        }
+    }
+    before.extend(parser.errors().iter().map(|error| shift(error, |position| position + old.begin())));
+    before.append(&mut after);
+    Some((block.replace_with(green), before))
+}
+
+// Where an error and its suggested fix are.
+fn positions(error: &ParseError) -> Vec<u32> {
+    let mut positions = vec![error.location.begin(), error.location.end()];
+    for edit in error.suggestion.iter().flat_map(|suggestion| &suggestion.edits) {
+        positions.extend([edit.span.begin(), edit.span.end()]);
+    }
+    positions
+}
+
+fn shift(error: &ParseError, f: impl Fn(u32) -> u32) -> ParseError {
+    let span = |span: Span| Span::new(f(span.begin()), f(span.end()));
+    let mut error = error.clone();
+    error.location = span(error.location);
+    for edit in error.suggestion.iter_mut().flat_map(|suggestion| &mut suggestion.edits) {
+        edit.span = span(edit.span);
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+
+    const CODE: &str = "def f(a) {\n  if (a) { g(a) }\n  return a\n}\n\ndef h() {\n  // unchanged\n  return 1\n}\n";
+
+    fn edit(begin: u32, end: u32, replacement: &str) -> Edit {
+        Edit { span: Span::new(begin, end), replacement: replacement.to_string() }
+    }
+
+    // Applies `edit` to `code` incrementally, checks that the result is
+    // what parsing the new text gives, and returns it.
+    fn check(code: &str, edit: &Edit) -> (SyntaxNode, SyntaxNode) {
+        let (root, errors) = parse_tree(code);
+        let (reparsed, reparsed_errors) = reparse(&root, &errors, edit);
+        let mut text = code.to_string();
+        text.replace_range(edit.span.begin() as usize..edit.span.end() as usize, &edit.replacement);
+        let (expected, expected_errors) = parse_tree(&text);
+        assert_eq!(reparsed.text(), text);
+        assert_eq!(reparsed.green(), expected.green(), "{:?}", text);
+        assert_eq!(reparsed_errors, expected_errors, "{:?}", text);
+        assert_eq!(root.text(), code);
+        (root, reparsed)
+    }
+
+    fn functions(root: &SyntaxNode) -> Vec<SyntaxNode> {
+        root.descendants().into_iter().filter(|node| node.kind() == SyntaxKind::Function).collect()
+    }
+
+    #[test]
+    fn reparses_the_innermost_block() {
+        // `g(a)` becomes `g(ab)`.
+        let (root, reparsed) = check(CODE, &edit(25, 25, "b"));
+        let (old, new) = (functions(&root), functions(&reparsed));
+        assert!(Rc::ptr_eq(old[1].green(), new[1].green()));
+        let params = |function: &SyntaxNode| function.children()[1].green().clone();
+        assert!(Rc::ptr_eq(&params(&old[0]), &params(&new[0])));
+        assert!(!Rc::ptr_eq(old[0].green(), new[0].green()));
+        // Only the statements of the edited block are new.
+        let first_return = |root: &SyntaxNode| {
+            root.descendants().into_iter().find(|node| node.kind() == SyntaxKind::ReturnStmt).unwrap()
+        };
+        assert!(Rc::ptr_eq(first_return(&root).green(), first_return(&reparsed).green()));
+    }
+
+    #[test]
+    fn matches_a_full_reparse() {
+        let edits = [
+            edit(24, 25, "x, y"),
+            edit(31, 39, "throw a;"),
+            edit(21, 21, "var [p, q] = r; "),
+            edit(70, 78, "return h()"),
+            edit(11, 11, "/* note */"),
+            edit(22, 26, ""),
+            // Errors in the block.
+            edit(24, 25, "a 1"),
+            edit(31, 39, "return )"),
+            // Edits that do not stay within a block.
+            edit(24, 24, "}"),
+            edit(24, 24, "{"),
+            edit(70, 70, "/*"),
+            edit(38, 38, "'"),
+            edit(42, 42, "var x = 1\n"),
+            edit(6, 7, "b"),
+            edit(0, CODE.len() as u32, ""),
+        ];
+        for edit in &edits {
+            check(CODE, edit);
+        }
+    }
+
+    #[test]
+    fn keeps_errors_outside_the_block() {
+        let code = "x = (1;\ndef f() { a; }\ny = ]\n";
+        let (root, errors) = parse_tree(code);
+        assert_eq!(errors.len(), 2);
+        let (reparsed, reparsed_errors) = reparse(&root, &errors, &edit(18, 19, "a 1"));
+        assert!(Rc::ptr_eq(root.children()[0].green(), reparsed.children()[0].green()));
+        assert_eq!(reparsed_errors.len(), 3);
+        assert_eq!(reparsed_errors[0], errors[0]);
+        assert_eq!(reparsed_errors[1].location, Span::new(20, 21));
+        assert_eq!(reparsed_errors[2].location, Span::new(errors[1].location.begin() + 2, errors[1].location.end() + 2));
+        check(code, &edit(18, 19, "a 1"));
+        check(code, &edit(18, 19, "[b] = c"));
+    }
+}
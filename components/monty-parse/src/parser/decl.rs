@@ -0,0 +1,448 @@
+// Declarations: variables, functions, classes, interfaces and enums, with
+// their annotations and modifiers.
+
+use lex::token::Token;
+use crate::ast::*;
+use super::{describe, ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // A declaration with optional annotations and modifiers in front. Only
+    // `final` may precede a variable declaration.
+    pub(super) fn declaration(&mut self) -> Result<Decl, ParseError> {
+        let begin = self.peek_span().begin();
+        let annotations = self.annotations()?;
+        let before_modifiers = self.checkpoint();
+        let modifiers = self.modifiers()?;
+        let kind = match self.peek() {
+            Token::Function => DeclKind::Function(self.function(true)?),
+            Token::Class => DeclKind::Class(self.class()?),
+            Token::Interface => DeclKind::Interface(self.interface()?),
+            Token::Enum => DeclKind::Enum(self.enumeration()?),
+            _ if modifiers.iter().all(|modifier| *modifier == Modifier::Final) => {
+                // `final` is read again as the kind of variable.
+                self.rewind(before_modifiers);
+                if !self.at_var_declaration() {
+                    let token = self.peek();
+                    return Err(self.error(self.peek_span(), format!("expected declaration, found {}", describe(&token))));
+                }
+                let decl = self.var_declaration()?;
+                let mut decl = self.decl(begin, DeclKind::Var(decl));
+                decl.annotations = annotations;
+                return Ok(decl);
+            },
+            _ => {
+                let token = self.peek();
+                return Err(self.error(self.peek_span(), format!("expected declaration, found {}", describe(&token))));
+            },
+        };
+        let mut decl = self.decl(begin, kind);
+        decl.annotations = annotations;
+        decl.modifiers = modifiers;
+        Ok(decl)
+    }
+
+    // `@name` and `@name(arguments)`.
+    fn annotations(&mut self) -> Result<Vec<Annotation>, ParseError> {
+        let mut annotations = Vec::new();
+        while self.peek() == Token::At {
+            let begin = self.peek_span().begin();
+            self.bump();
+            let name = self.path()?;
+            let arguments = match self.peek() {
+                Token::Lparen => {
+                    self.bump();
+                    Some(self.list(Token::Rparen, |parser| parser.assignment())?)
+                },
+                _ => None,
+            };
+            annotations.push(Annotation { id: self.ids.fresh(), span: self.span_from(begin), name, arguments });
+        }
+        Ok(annotations)
+    }
+
+    fn modifiers(&mut self) -> Result<Vec<Modifier>, ParseError> {
+        let mut modifiers: Vec<Modifier> = Vec::new();
+        loop {
+            let modifier = match self.peek() {
+                Token::Public => Modifier::Public,
+                Token::Protected => Modifier::Protected,
+                Token::Private => Modifier::Private,
+                Token::Static => Modifier::Static,
+                Token::Abstract => Modifier::Abstract,
+                Token::Final => Modifier::Final,
+                Token::Native => Modifier::Native,
+                _ => return Ok(modifiers),
+            };
+            let token = self.bump();
+            let access = |modifier: &Modifier| matches!(modifier, Modifier::Public | Modifier::Protected | Modifier::Private);
+            if modifiers.contains(&modifier) {
+                return Err(self.error(self.prev, format!("duplicate modifier {}", describe(&token))));
+            }
+            if access(&modifier) && modifiers.iter().any(access) {
+                return Err(self.error(self.prev, "conflicting access modifiers".to_string()));
+            }
+            modifiers.push(modifier);
+        }
+    }
+
+    // `class Name extends Base implements A, B { members }`
+    fn class(&mut self) -> Result<Class, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Class)?;
+        let name = self.binding_identifier()?;
+        let extends = match self.eat(&Token::Extends) {
+            true => Some(self.parse_type()?),
+            false => None,
+        };
+        let mut implements = Vec::new();
+        if self.eat(&Token::Implements) {
+            implements.push(self.parse_type()?);
+            while self.eat(&Token::Comma) {
+                implements.push(self.parse_type()?);
+            }
+        }
+        self.expect(Token::Lbrace)?;
+        let mut members = Vec::new();
+        while !matches!(self.peek(), Token::Rbrace | Token::Eos) {
+            let member = self.recovering(
+                |parser| parser.class_member(&name.name),
+                |parser, begin| ClassMember {
+                    id: parser.ids.fresh(),
+                    span: parser.span_from(begin),
+                    annotations: Vec::new(),
+                    modifiers: Vec::new(),
+                    kind: ClassMemberKind::Error,
+                },
+            );
+            members.push(member);
+        }
+        self.expect(Token::Rbrace)?;
+        Ok(Class { id: self.ids.fresh(), span: self.span_from(begin), name, extends, implements, members })
+    }
+
+    // A field `[Type] name [= value];`, a method `def name(...) {...}` or a
+    // constructor, which is named after the class.
+    fn class_member(&mut self, class_name: &str) -> Result<ClassMember, ParseError> {
+        let begin = self.peek_span().begin();
+        let annotations = self.annotations()?;
+        let modifiers = self.modifiers()?;
+        let kind = match self.peek() {
+            Token::Function => {
+                let function_begin = self.peek_span().begin();
+                self.bump();
+                let name = self.member_name_here()?;
+                let function = self.function_tail(function_begin, None, true)?;
+                let bodiless = modifiers.iter().any(|modifier| matches!(modifier, Modifier::Abstract | Modifier::Native));
+                match (&function.body, bodiless) {
+                    (FunctionBody::None, false) => {
+                        return Err(self.error(function.span, "method needs a body unless abstract or native".to_string()));
+                    },
+                    (FunctionBody::Block(body), true) => {
+                        return Err(self.error(body.span, "abstract and native methods cannot have a body".to_string()));
+                    },
+                    _ => {},
+                }
+                ClassMemberKind::Method { name, function }
+            },
+            Token::Identifier(name) if name.iter().copied().eq(class_name.chars()) && self.constructor_ahead() => {
+                let name = self.binding_identifier()?;
+                ClassMemberKind::Constructor(self.function_tail(name.span.begin(), Some(name), false)?)
+            },
+            _ => {
+                self.eat(&Token::Var);
+                let ty = match self.type_ahead() {
+                    true => Some(self.parse_type()?),
+                    false => None,
+                };
+                let name = self.member_name_here()?;
+                let init = match self.eat(&Token::Assign) {
+                    true => Some(self.assignment()?),
+                    false => None,
+                };
+                self.expect(Token::Semicolon)?;
+                ClassMemberKind::Field { name, ty, init }
+            },
+        };
+        Ok(ClassMember { id: self.ids.fresh(), span: self.span_from(begin), annotations, modifiers, kind })
+    }
+
+    fn constructor_ahead(&mut self) -> bool {
+        let checkpoint = self.checkpoint();
+        self.bump();
+        let found = self.peek() == Token::Lparen;
+        self.rewind(checkpoint);
+        found
+    }
+
+    // The name of a field or method, which may be private.
+    fn member_name_here(&mut self) -> Result<MemberName, ParseError> {
+        match self.bump() {
+            Token::Identifier(name) => Ok(MemberName::Public(self.identifier(name))),
+            Token::PrivateName(name) => Ok(MemberName::Private(self.identifier(name))),
+            token => Err(self.error(self.prev, format!("expected member name, found {}", describe(&token)))),
+        }
+    }
+
+    // `interface Name extends A, B { def m(...): T; }`
+    fn interface(&mut self) -> Result<Interface, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Interface)?;
+        let name = self.binding_identifier()?;
+        let mut extends = Vec::new();
+        if self.eat(&Token::Extends) {
+            extends.push(self.parse_type()?);
+            while self.eat(&Token::Comma) {
+                extends.push(self.parse_type()?);
+            }
+        }
+        self.expect(Token::Lbrace)?;
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), Token::Rbrace | Token::Eos) {
+            let method_begin = self.peek_span().begin();
+            self.expect(Token::Function)?;
+            let method_name = self.binding_identifier()?;
+            let method = self.function_tail(method_begin, Some(method_name), true)?;
+            if let FunctionBody::Block(body) = &method.body {
+                return Err(self.error(body.span, "interface methods cannot have a body".to_string()));
+            }
+            methods.push(method);
+        }
+        self.expect(Token::Rbrace)?;
+        Ok(Interface { id: self.ids.fresh(), span: self.span_from(begin), name, extends, methods })
+    }
+
+    // `enum Name { A, B = value }`
+    fn enumeration(&mut self) -> Result<Enum, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Enum)?;
+        let name = self.binding_identifier()?;
+        self.expect(Token::Lbrace)?;
+        let variants = self.list(Token::Rbrace, |parser| {
+            let begin = parser.peek_span().begin();
+            let name = parser.binding_identifier()?;
+            let value = match parser.eat(&Token::Assign) {
+                true => Some(parser.assignment()?),
+                false => None,
+            };
+            Ok(EnumVariant { id: parser.ids.fresh(), span: parser.span_from(begin), name, value })
+        })?;
+        Ok(Enum { id: self.ids.fresh(), span: self.span_from(begin), name, variants })
+    }
+
+    // Whether a variable declaration starts at the lookahead token: `var`,
+    // `const`, `final`, a primitive type other than void, or a type
+    // followed by a variable name as in `string s = 'x'`.
+    pub(super) fn at_var_declaration(&mut self) -> bool {
+        match self.peek() {
+            Token::Var | Token::Const | Token::Final => true,
+            Token::Void => false,
+            Token::Identifier(_) | Token::Lparen => self.typed_name_ahead(),
+            token => Primitive::from_token(&token).is_some(),
+        }
+    }
+
+    // `var x = 1, y`, `const z = 3`, `final T t` or `int i = 0`, without a
+    // closing semicolon.
+    pub(super) fn var_declaration(&mut self) -> Result<VarDecl, ParseError> {
+        let begin = self.peek_span().begin();
+        let kind = match self.peek() {
+            Token::Var => VarKind::Var,
+            Token::Const => VarKind::Const,
+            Token::Final => VarKind::Final,
+            _ => VarKind::Typed,
+        };
+        let ty = match kind {
+            VarKind::Var => {
+                self.bump();
+                None
+            },
+            VarKind::Typed => Some(self.parse_type()?),
+            // The type is optional after const and final.
+            VarKind::Const | VarKind::Final => {
+                self.bump();
+                match self.typed_name_ahead() {
+                    true => Some(self.parse_type()?),
+                    false => None,
+                }
+            },
+        };
+        let mut declarators = Vec::new();
+        loop {
+            let begin = self.peek_span().begin();
+            let pattern = self.binding_pattern()?;
+            let init = match self.eat(&Token::Assign) {
+                true => Some(self.assignment()?),
+                false => None,
+            };
+            // `for (const k in o)` gets its value from the loop.
+            if init.is_none() && !(self.no_in && self.peek() == Token::In) {
+                if !matches!(pattern.kind, PatternKind::Identifier(_)) {
+                    return Err(self.error(pattern.span, "missing initializer in destructuring declaration".to_string()));
+                }
+                if kind == VarKind::Const {
+                    return Err(self.error(pattern.span, "missing initializer in const declaration".to_string()));
+                }
+            }
+            declarators.push(Declarator { id: self.ids.fresh(), span: self.span_from(begin), pattern, init });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(VarDecl { id: self.ids.fresh(), span: self.span_from(begin), kind, ty, declarators })
+    }
+
+    // Looks ahead for a type followed by a name that is then initialized,
+    // continued or ended, which an expression cannot start with. After a
+    // type that could be an expression, a line break before the name ends
+    // the statement instead, and after a nullable type, a `:` past the
+    // initializer makes `a ? b = 1 : c` a conditional.
+    fn typed_name_ahead(&mut self) -> bool {
+        let checkpoint = self.checkpoint();
+        let named = matches!(self.peek(), Token::Identifier(_));
+        let ty = self.parse_type();
+        let nullable = matches!(&ty, Ok(ty) if matches!(ty.kind, TypeKind::Nullable(_)));
+        let found = ty.is_ok()
+            && matches!(self.peek(), Token::Identifier(_))
+            && !(named && self.newline)
+            && {
+                self.bump();
+                let next = self.peek();
+                matches!(next, Token::Assign | Token::Comma | Token::Semicolon | Token::In | Token::Rbrace | Token::Eos)
+                    || (self.newline && next != Token::Colon)
+            }
+            && !(nullable && self.eat(&Token::Assign) && self.colon_ahead());
+        self.rewind(checkpoint);
+        found
+    }
+
+    // Whether the expression at the lookahead token is followed by a `:`
+    // that no `?` within it takes. Tokens are skipped rather than parsed.
+    fn colon_ahead(&mut self) -> bool {
+        let (mut depth, mut conditionals) = (0, 0);
+        loop {
+            match self.peek() {
+                Token::Lparen | Token::Lbrack | Token::Lbrace => depth += 1,
+                Token::Rparen | Token::Rbrack | Token::Rbrace if depth == 0 => return false,
+                Token::Rparen | Token::Rbrack | Token::Rbrace => depth -= 1,
+                Token::Semicolon | Token::Comma if depth == 0 => return false,
+                Token::Eos => return false,
+                Token::Conditional if depth == 0 => conditionals += 1,
+                Token::Colon if depth == 0 && conditionals == 0 => return true,
+                Token::Colon if depth == 0 => conditionals -= 1,
+                _ => {},
+            }
+            self.bump();
+        }
+    }
+
+    pub(super) fn decl(&mut self, begin: u32, kind: DeclKind) -> Decl {
+        Decl {
+            id: self.ids.fresh(),
+            span: self.span_from(begin),
+            annotations: Vec::new(),
+            modifiers: Vec::new(),
+            kind,
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::sexp::{error, parse};
+
+    #[test]
+    fn parses_variable_declarations() {
+        assert_eq!(parse("int x = 1;"), "(int (x 1))");
+        assert_eq!(parse("var y = 2, z"), "(var (y 2) (z))");
+        assert_eq!(parse("const z = 3;"), "(const (z 3))");
+        assert_eq!(parse("final long n\nfinal m = n"), "(final long (n)) (final (m n))");
+        assert_eq!(parse("string s = 'hello world';"), "(string (s 'hello world'))");
+        assert_eq!(parse("List<List<int>> xs = [], ys;"), "(List<List<int>> (xs []) (ys))");
+        assert_eq!(parse("int?[] a\nMap<K, V> m"), "(int?[] (a)) (Map<K, V> (m))");
+        assert_eq!(parse("(int) => bool f = g;"), "((int) => bool (f g))");
+        assert_eq!(error("const c;"), (6, 7, "missing initializer in const declaration".to_string()));
+        assert_eq!(error("int 1;"), (4, 5, "expected identifier, found number `1`".to_string()));
+    }
+
+    #[test]
+    fn keeps_expressions_that_look_like_types() {
+        assert_eq!(parse("a < b;"), "(< a b)");
+        assert_eq!(parse("x >>= 1; a[0] = b"), "(>>= x 1) (= ([] a 0) b)");
+        assert_eq!(parse("a ? b : c"), "(? a b c)");
+        assert_eq!(parse("void 0"), "(void 0)");
+        assert_eq!(parse("l: x"), "(l: x)");
+        assert_eq!(parse("a\nb = 1"), "a (= b 1)");
+        assert_eq!(parse("init\ncount = 0"), "init (= count 0)");
+        assert_eq!(parse("int\nx = 1"), "(int (x 1))");
+        assert_eq!(parse("a ? b = 1 : c;"), "(? a (= b 1) c)");
+        assert_eq!(parse("x ? y = 2 : z"), "(? x (= y 2) z)");
+        assert_eq!(parse("a ? b = c ? d : e : f;"), "(? a (= b (? c d e)) f)");
+        assert_eq!(parse("T? t = c ? d : e;"), "(T? (t (? c d e)))");
+        assert_eq!(parse("T? t = f(x ? y : z), u;"), "(T? (t (call f (? x y z))) (u))");
+    }
+
+    #[test]
+    fn parses_classes() {
+        assert_eq!(
+            parse("class A extends B<int> implements C, D {}"),
+            "(class A extends B<int> implements C D {})"
+        );
+        assert_eq!(
+            parse(
+                "public abstract class Shape {\n\
+                     private static int count = 0;\n\
+                     #id;\n\
+                     var name = 'shape';\n\
+                     Shape(string name) { super(); this.name = name }\n\
+                     public abstract def area(): double;\n\
+                     @Override def toString(): string { return name }\n\
+                     static def #next() { return count++ }\n\
+                 }"
+            ),
+            "(public abstract (class Shape {\
+                 (private static (field int count 0)) \
+                 (field #id) \
+                 (field name 'shape') \
+                 (constructor (def Shape (string name) {(call super) (= (. this name) name)})) \
+                 (public abstract (method area (def () : double))) \
+                 (@Override (method toString (def () : string {(return name)}))) \
+                 (static (method #next (def () {(return (count++))})))\
+             }))"
+        );
+        assert_eq!(parse("final class F {} final int x = 1"), "(final (class F {})) (final int (x 1))");
+        assert_eq!(parse("@Test @Tag('slow') def f() {}"), "(@Test @Tag('slow') (def f () {}))");
+    }
+
+    #[test]
+    fn rejects_bad_members_and_modifiers() {
+        assert_eq!(error("class A { def m(); }"), (10, 18, "method needs a body unless abstract or native".to_string()));
+        assert_eq!(error("class A { native def m() {} }"), (25, 27, "abstract and native methods cannot have a body".to_string()));
+        assert_eq!(error("public private class A {}"), (7, 14, "conflicting access modifiers".to_string()));
+        assert_eq!(error("static static def f() {}"), (7, 13, "duplicate modifier `static`".to_string()));
+        assert_eq!(error("static x = 1;"), (7, 8, "expected declaration, found identifier `x`".to_string()));
+        assert_eq!(error("class A { 1 }"), (10, 11, "expected member name, found number `1`".to_string()));
+    }
+
+    #[test]
+    fn parses_interfaces_and_enums() {
+        assert_eq!(
+            parse("interface Shape extends Named, Sized { def area(): double; def scale(double by) throws E; }"),
+            "(interface Shape extends Named Sized {(def area () : double) (def scale (double by) throws E)})"
+        );
+        assert_eq!(error("interface I { def m() {} }"), (22, 24, "interface methods cannot have a body".to_string()));
+        assert_eq!(parse("enum Color { Red, Green = 2, Blue = Green * 2, }"), "(enum Color {Red (Green 2) (Blue (* Green 2))})");
+        assert_eq!(parse("enum Empty {}"), "(enum Empty {})");
+    }
+
+    #[test]
+    fn parses_declarations_in_for() {
+        assert_eq!(parse("for (int i = 0, j; i < n; i++) ;"), "(for (int (i 0) (j)) (< i n) (i++) ;)");
+        assert_eq!(parse("for (var k in o) ;"), "(for-in (var (k)) o ;)");
+        assert_eq!(parse("for (const k in o) ;"), "(for-in (const (k)) o ;)");
+        assert_eq!(parse("for (string k in o) ;"), "(for-in (string (k)) o ;)");
+        assert_eq!(error("for (var a, b in o) ;"), (5, 13, "for-in declaration must be a single variable without initializer".to_string()));
+        assert_eq!(error("for (var a = 1 in o) ;"), (5, 14, "for-in declaration must be a single variable without initializer".to_string()));
+    }
+}
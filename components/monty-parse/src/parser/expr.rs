@@ -0,0 +1,446 @@
+// Expressions, parsed by precedence climbing. Infix operators bind as
+// tightly as Token::precedence() says, or as the scanner Config says for
+// custom operators, so the lexer and the parser share one table.
+
+use lex::scanner::{Associativity, Span};
+use lex::token::Token;
+use crate::ast::*;
+use super::{describe, ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // Expression, including comma sequences.
+    pub fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.binary(Token::Comma.precedence())
+    }
+
+    // Expression without a top-level comma, as in arguments and
+    // initializers.
+    pub fn assignment(&mut self) -> Result<Expr, ParseError> {
+        self.binary(Token::Assign.precedence())
+    }
+
+    // Parses operators binding at least as tightly as `min`.
+    fn binary(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = self.peek();
+            let precedence = self.precedence(&op);
+            if precedence == 0 || precedence < min {
+                return Ok(left);
+            }
+            let begin = left.span.begin();
+            let kind = match op {
+                Token::Comma => {
+                    let mut exprs = vec![left];
+                    while self.eat(&Token::Comma) {
+                        exprs.push(self.assignment()?);
+                    }
+                    ExprKind::Sequence(exprs)
+                },
+                Token::Conditional => {
+                    self.bump();
+                    let then = self.with_in(true, |parser| parser.assignment())?;
+                    self.expect(Token::Colon)?;
+                    let otherwise = self.assignment()?;
+                    ExprKind::Conditional {
+                        condition: Box::new(left),
+                        then: Box::new(then),
+                        otherwise: Box::new(otherwise),
+                    }
+                },
+                _ if op.is_assignment_op() => {
+                    if op != Token::Assign {
+                        self.check_target(&left)?;
+                    }
+                    let target = self.assignment_target(left)?;
+                    self.bump();
+                    // Right associative: a = b = c is a = (b = c).
+                    let value = self.binary(precedence)?;
+                    ExprKind::Assign { op, target: Box::new(target), value: Box::new(value) }
+                },
+                Token::CustomOperator(symbol) => {
+                    let associativity = self.scanner.config().operator(symbol).associativity;
+                    self.bump();
+                    let right = match associativity {
+                        Associativity::Right => self.binary(precedence)?,
+                        _ => self.binary(precedence.saturating_add(1))?,
+                    };
+                    if associativity == Associativity::None && self.peek() == op {
+                        let spelling = &self.scanner.config().operator(symbol).spelling;
+                        return Err(self.error(
+                            self.peek_span(),
+                            format!("operator `{}` cannot be chained", spelling),
+                        ));
+                    }
+                    ExprKind::Binary { op, left: Box::new(left), right: Box::new(right) }
+                },
+                _ => {
+                    self.bump();
+                    let right = self.binary(precedence.saturating_add(1))?;
+                    ExprKind::Binary { op, left: Box::new(left), right: Box::new(right) }
+                },
+            };
+            left = self.expr(begin, kind);
+        }
+    }
+
+    fn precedence(&self, token: &Token) -> u8 {
+        match token {
+            Token::CustomOperator(symbol) => self.scanner.config().operator(*symbol).precedence,
+            Token::In if self.no_in => 0,
+            _ => token.precedence(),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let op = self.peek();
+        let begin = self.peek_span().begin();
+        if matches!(op, Token::Identifier(_) | Token::Lparen) {
+            if let Some(arrow) = self.arrow_function()? {
+                return Ok(arrow);
+            }
+        }
+        if op.is_unary_op() {
+            self.bump();
+            let operand = self.unary()?;
+            return Ok(self.expr(begin, ExprKind::Unary { op, operand: Box::new(operand) }));
+        }
+        if op.is_count_op() {
+            self.bump();
+            let operand = self.unary()?;
+            self.check_target(&operand)?;
+            let kind = ExprKind::Update { op, prefix: true, operand: Box::new(operand) };
+            return Ok(self.expr(begin, kind));
+        }
+        let operand = self.postfix()?;
+        // A line break before ++ or -- ends the expression instead.
+        let op = self.peek();
+        if op.is_count_op() && !self.newline {
+            self.check_target(&operand)?;
+            self.bump();
+            let kind = ExprKind::Update { op, prefix: false, operand: Box::new(operand) };
+            return Ok(self.expr(begin, kind));
+        }
+        Ok(operand)
+    }
+
+    // A primary or `new` expression followed by any number of member
+    // accesses, indexes and calls.
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let begin = self.peek_span().begin();
+        let expr = match self.peek() {
+            Token::New => self.new_expression()?,
+            _ => self.primary()?,
+        };
+        self.suffixes(begin, expr, true)
+    }
+
+    // `new C` and `new C(arguments)`. The callee takes member accesses but
+    // not calls, so `new a.B().c()` calls c on the new object.
+    fn new_expression(&mut self) -> Result<Expr, ParseError> {
+        let begin = self.peek_span().begin();
+        self.bump();
+        let callee_begin = self.peek_span().begin();
+        let callee = match self.peek() {
+            Token::New => self.new_expression()?,
+            _ => self.primary()?,
+        };
+        let callee = self.suffixes(callee_begin, callee, false)?;
+        let arguments = match self.peek() {
+            Token::Lparen => Some(self.arguments()?),
+            _ => None,
+        };
+        Ok(self.expr(begin, ExprKind::New { callee: Box::new(callee), arguments }))
+    }
+
+    fn suffixes(&mut self, begin: u32, mut expr: Expr, calls: bool) -> Result<Expr, ParseError> {
+        loop {
+            let kind = match self.peek() {
+                Token::Period => {
+                    self.bump();
+                    let property = self.member_name()?;
+                    ExprKind::Member { object: Box::new(expr), property }
+                },
+                Token::Lbrack => {
+                    self.bump();
+                    let index = self.with_in(true, |parser| parser.expression())?;
+                    self.expect(Token::Rbrack)?;
+                    ExprKind::Index { object: Box::new(expr), index: Box::new(index) }
+                },
+                Token::Lparen if calls => {
+                    let arguments = self.arguments()?;
+                    ExprKind::Call { callee: Box::new(expr), arguments }
+                },
+                _ => return Ok(expr),
+            };
+            expr = self.expr(begin, kind);
+        }
+    }
+
+    // The name after a '.'; keywords are allowed there, as in `a.new`.
+    fn member_name(&mut self) -> Result<MemberName, ParseError> {
+        match self.bump() {
+            Token::PrivateName(name) => Ok(MemberName::Private(self.identifier(name))),
+            token => match self.property_name(&token) {
+                Some(name) => Ok(MemberName::Public(self.identifier(name))),
+                None => Err(self.error(
+                    self.prev,
+                    format!("expected property name, found {}", describe(&token)),
+                )),
+            },
+        }
+    }
+
+    // The spelling of a token usable as a property name.
+    fn property_name(&self, token: &Token) -> Option<Vec<char>> {
+        match token {
+            Token::Identifier(name) => Some(name.clone()),
+            Token::NullLiteral | Token::TrueLiteral | Token::FalseLiteral => Some(token.clone().value().chars().collect()),
+            _ if token.is_keyword() || token.is_reserved_word() => {
+                Some(token.clone().value().chars().collect())
+            },
+            _ => None,
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect(Token::Lparen)?;
+        self.list(Token::Rparen, |parser| parser.assignment())
+    }
+
+    // `{a: 1, "b": 2, 3: c, d}`, after the '{'. `{d}` is short for `{d: d}`.
+    fn object(&mut self) -> Result<Vec<Property>, ParseError> {
+        self.list(Token::Rbrace, |parser| {
+            let begin = parser.peek_span().begin();
+            let shorthand = matches!(parser.peek(), Token::Identifier(_));
+            let key = parser.property_key()?;
+            let value = match &key {
+                PropertyKey::Identifier(name) if shorthand && matches!(parser.peek(), Token::Comma | Token::Rbrace) => {
+                    let name = Identifier { id: parser.ids.fresh(), span: name.span, name: name.name.clone() };
+                    Expr { id: parser.ids.fresh(), span: name.span, kind: ExprKind::Identifier(name) }
+                },
+                _ => {
+                    parser.expect(Token::Colon)?;
+                    parser.assignment()?
+                },
+            };
+            Ok(Property { id: parser.ids.fresh(), span: parser.span_from(begin), key, value })
+        })
+    }
+
+    // A string, number or name before the ':' of a property.
+    pub(super) fn property_key(&mut self) -> Result<PropertyKey, ParseError> {
+        match self.bump() {
+            Token::String(value) => Ok(PropertyKey::String(StringLiteral {
+                id: self.ids.fresh(),
+                span: self.prev,
+                value: value.into_iter().collect(),
+            })),
+            Token::Number(digits) => Ok(PropertyKey::Number(digits.into_iter().collect())),
+            token => match self.property_name(&token) {
+                Some(name) => Ok(PropertyKey::Identifier(self.identifier(name))),
+                None => Err(self.error(self.prev, format!("expected property name, found {}", describe(&token)))),
+            },
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let begin = self.peek_span().begin();
+        if self.peek() == Token::Function {
+            let function = self.function(false)?;
+            return Ok(self.expr(begin, ExprKind::Function(Box::new(function))));
+        }
+        if let Some(kind) = literal(&self.peek()) {
+            self.bump();
+            return Ok(self.expr(begin, kind));
+        }
+        let kind = match self.peek() {
+            Token::Identifier(_) => ExprKind::Identifier(self.binding_identifier()?),
+            Token::Lparen => {
+                self.bump();
+                let inner = self.with_in(true, |parser| parser.expression())?;
+                self.expect(Token::Rparen)?;
+                ExprKind::Paren(Box::new(inner))
+            },
+            Token::Lbrack => {
+                self.bump();
+                ExprKind::Array(self.list(Token::Rbrack, |parser| {
+                    let begin = parser.peek_span().begin();
+                    match parser.eat_ellipsis() {
+                        true => {
+                            let operand = parser.assignment()?;
+                            Ok(parser.expr(begin, ExprKind::Spread(Box::new(operand))))
+                        },
+                        false => parser.assignment(),
+                    }
+                })?)
+            },
+            Token::Lbrace => {
+                self.bump();
+                ExprKind::Object(self.object()?)
+            },
+            token => return self.missing_expression(token),
+        };
+        Ok(self.expr(begin, kind))
+    }
+
+    // No expression starts at `token`. Before a token that ends or
+    // separates expressions, one is taken to be missing: the error is
+    // recorded and an empty error node stands in for it.
+    fn missing_expression(&mut self, token: Token) -> Result<Expr, ParseError> {
+        let location = self.peek_span();
+        let error = self.unexpected(token.clone(), location);
+        match token {
+            Token::Semicolon | Token::Comma | Token::Rparen | Token::Rbrack | Token::Rbrace | Token::Eos => {
+                self.report(error);
+                let at = Span::new(location.begin(), location.begin());
+                Ok(Expr { id: self.ids.fresh(), span: at, kind: ExprKind::Error })
+            },
+            _ => Err(error),
+        }
+    }
+
+    // Only names, members and elements can be assigned to.
+    pub(super) fn check_target(&self, expr: &Expr) -> Result<(), ParseError> {
+        match &expr.kind {
+            ExprKind::Identifier(_) | ExprKind::Member { .. } | ExprKind::Index { .. } => Ok(()),
+            ExprKind::Paren(inner) => self.check_target(inner),
+            _ => Err(self.error(expr.span, "invalid assignment target".to_string())),
+        }
+    }
+
+}
+
+// The literal spelled by `token`, if it is one.
+fn literal(token: &Token) -> Option<ExprKind> {
+    match token {
+        Token::Number(digits) => Some(ExprKind::Number(digits.iter().collect())),
+        Token::String(chars) => Some(ExprKind::String(chars.iter().collect())),
+        Token::CharLiteral(ch) => Some(ExprKind::Char(*ch)),
+        Token::TrueLiteral => Some(ExprKind::Bool(true)),
+        Token::FalseLiteral => Some(ExprKind::Bool(false)),
+        Token::NullLiteral => Some(ExprKind::Null),
+        Token::This => Some(ExprKind::This),
+        Token::Super => Some(ExprKind::Super),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lex::scanner::{Config, Scanner, Span};
+    use crate::parser::parse_expression;
+    use crate::parser::sexp::{expr, expr_error, sexp};
+
+    #[test]
+    fn parses_sample() {
+        let expr = parse_expression("(2 + 2) / 3").unwrap();
+        assert_eq!(sexp(&expr), "(/ (paren (+ 2 2)) 3)");
+        assert_eq!(expr.span, Span::new(0, 11));
+        match &expr.kind {
+            ExprKind::Binary { left, right, .. } => {
+                assert_eq!(left.span, Span::new(0, 7));
+                assert_eq!(right.span, Span::new(10, 11));
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+        // The rest of the sample line is not a valid assignment.
+        assert_eq!(expr_error("(2 + 2) / 3 = ?"), (0, 11, "invalid assignment target".to_string()));
+    }
+
+    #[test]
+    fn respects_precedence_and_associativity() {
+        assert_eq!(expr("a + b * c - d"), "(- (+ a (* b c)) d)");
+        assert_eq!(expr("a || b && c | d ^ e & f"), "(|| a (&& b (| c (^ d (& e f)))))");
+        assert_eq!(expr("a == b < c << d + e"), "(== a (< b (<< c (+ d e))))");
+        assert_eq!(expr("a - b - c"), "(- (- a b) c)");
+        assert_eq!(expr("x in y != true"), "(!= (in x y) true)");
+        assert_eq!(expr("a = b += c ? d : e ? f : g"), "(= a (+= b (? c d (? e f g))))");
+        assert_eq!(expr("a ? b = 1 : c = 2"), "(? a (= b 1) (= c 2))");
+        assert_eq!(expr("a, b = 1, 'c'"), "(, a (= b 1) 'c')");
+    }
+
+    #[test]
+    fn parses_unary_and_update() {
+        assert_eq!(expr("-x++ + !y"), "(+ (- (x++)) (! y))");
+        assert_eq!(expr("typeof ~-null"), "(typeof (~ (- null)))");
+        assert_eq!(expr("--(x)"), "(--(paren x))");
+        assert_eq!(expr_error("++1"), (2, 3, "invalid assignment target".to_string()));
+        assert_eq!(expr_error("this++"), (0, 4, "invalid assignment target".to_string()));
+
+        // A line break before a postfix operator ends the expression.
+        let mut parser = Parser::new(Scanner::new("a\n++b", 0));
+        assert_eq!(sexp(&parser.expression().unwrap()), "a");
+        assert_eq!(parser.peek(), Token::Inc);
+    }
+
+    #[test]
+    fn parses_postfix_chains() {
+        assert_eq!(expr("s.lenght() > 4"), "(> (call (. s lenght)) 4)");
+        assert_eq!(expr("a.b[c + 1](x, y).#d"), "(. (call ([] (. a b) (+ c 1)) x y) #d)");
+        assert_eq!(expr("f(a = 1, (b, c),)"), "(call f (= a 1) (paren (, b c)))");
+        assert_eq!(expr("a.new.class"), "(. (. a new) class)");
+        assert_eq!(expr("super.m(this)"), "(call (. super m) this)");
+        assert_eq!(expr("a.b = c[0]++"), "(= (. a b) (([] c 0)++))");
+    }
+
+    #[test]
+    fn parses_new() {
+        assert_eq!(expr("new C"), "(new C)");
+        assert_eq!(expr("new C()"), "(new C ())");
+        assert_eq!(expr("new a.B(1, 2).c()"), "(call (. (new (. a B) (1 2)) c))");
+        assert_eq!(expr("new new C()()"), "(new (new C ()) ())");
+        assert_eq!(expr("new C.d"), "(new (. C d))");
+        let expr = parse_expression("new a.B(1)").unwrap();
+        assert_eq!(expr.span, Span::new(0, 10));
+    }
+
+    #[test]
+    fn parses_unary_keywords() {
+        assert_eq!(expr("delete a.b"), "(delete (. a b))");
+        assert_eq!(expr("typeof f() == 'x'"), "(== (typeof (call f)) 'x')");
+        assert_eq!(expr("void 0, !a[1]"), "(, (void 0) (! ([] a 1)))");
+    }
+
+    #[test]
+    fn parses_array_and_object_literals() {
+        assert_eq!(expr("[1, [2], ]"), "[1 [2]]");
+        assert_eq!(expr("[]"), "[]");
+        assert_eq!(expr("{a: 1, \"b\": 2, 3: [x], if: {}}"), "{a: 1, 'b': 2, 3: [x], if: {}}");
+        assert_eq!(expr("{a: b = c}.a"), "(. {a: (= b c)} a)");
+        assert_eq!(expr_error("{a 1}"), (3, 4, "expected `:`, found number `1`".to_string()));
+        assert_eq!(expr_error("[1 2]"), (3, 4, "expected `,`, found number `2`".to_string()));
+        assert_eq!(expr_error("a.+"), (2, 3, "expected property name, found `+`".to_string()));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(expr_error("1 = 2"), (0, 1, "invalid assignment target".to_string()));
+        assert_eq!(expr_error("a +"), (3, 3, "unexpected end of input".to_string()));
+        assert_eq!(expr_error("(a"), (2, 2, "expected `)`, found end of input".to_string()));
+        assert_eq!(expr_error("a ? b"), (5, 5, "expected `:`, found end of input".to_string()));
+        assert_eq!(expr_error("a b"), (2, 3, "unexpected identifier `b`".to_string()));
+        assert_eq!(expr_error("1 + 'open"), (4, 9, "unterminated string literal".to_string()));
+    }
+
+    #[test]
+    fn uses_scanner_config() {
+        let mut config = Config { comments: true, ..Default::default() };
+        config.add_operator("**", 14, Associativity::Right).unwrap();
+        config.add_operator("<>", 9, Associativity::None).unwrap();
+        let parse_with = |code: &str| {
+            let mut parser = Parser::new(Scanner::with_config(code, 0, config.clone()));
+            let expr = parser.expression()?;
+            parser.expect_end().map(|_| expr)
+        };
+        let expr = parse_with("a ** b ** /* c */ c * d // e").unwrap();
+        assert_eq!(sexp(&expr), "(* (op0 a (op0 b c)) d)");
+        assert_eq!(expr.span, Span::new(0, 23));
+        assert_eq!(sexp(&parse_with("a <> b + c == d").unwrap()), "(== (op1 a (+ b c)) d)");
+        let error = parse_with("a <> b <> c").unwrap_err();
+        assert_eq!(error.message, "operator `<>` cannot be chained");
+        assert_eq!(error.location, Span::new(7, 9));
+    }
+}
@@ -0,0 +1,259 @@
+// Functions: `def name(int a, b = 1, ...rest): T throws E { ... }`,
+// anonymous `def (...) { ... }` expressions and arrow functions.
+
+use lex::scanner::Span;
+use lex::token::Token;
+use crate::ast::*;
+use super::{ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // A function starting at `def`. Declarations must be named, function
+    // expressions may be.
+    pub(super) fn function(&mut self, named: bool) -> Result<Function, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Function)?;
+        let name = match self.peek() {
+            Token::Identifier(_) => Some(self.binding_identifier()?),
+            _ if named => Some(self.binding_identifier()?),
+            _ => None,
+        };
+        self.function_tail(begin, name, false)
+    }
+
+    // The parameters, return type, throws clause and body of a function
+    // whose head has been consumed. A signature may end in ';' instead of
+    // a body.
+    pub(super) fn function_tail(
+        &mut self,
+        begin: u32,
+        name: Option<Identifier>,
+        signature: bool,
+    ) -> Result<Function, ParseError> {
+        let params = self.params()?;
+        let return_type = self.return_type()?;
+        let mut throws = Vec::new();
+        if self.eat(&Token::Throws) {
+            throws.push(self.parse_type()?);
+            while self.eat(&Token::Comma) {
+                throws.push(self.parse_type()?);
+            }
+        }
+        let body = match self.peek() {
+            Token::Semicolon if signature => {
+                self.bump();
+                FunctionBody::None
+            },
+            _ => FunctionBody::Block(self.block()?),
+        };
+        Ok(Function {
+            id: self.ids.fresh(),
+            span: self.span_from(begin),
+            name,
+            params,
+            return_type,
+            throws,
+            body,
+            arrow: false,
+        })
+    }
+
+    // `x => body` or `(params): T => body`, if one starts at the lookahead
+    // token. Anything else is left unconsumed.
+    pub(super) fn arrow_function(&mut self) -> Result<Option<Expr>, ParseError> {
+        let begin = self.peek_span().begin();
+        let checkpoint = self.checkpoint();
+        let head = match self.peek() {
+            Token::Identifier(_) => self.binding_pattern().map(|pattern| {
+                let param = Param { id: self.ids.fresh(), span: pattern.span, pattern, ty: None, default: None, rest: false };
+                (vec![param], None)
+            }),
+            Token::Lparen if self.arrow_params_ahead() => {
+                self.params().and_then(|params| Ok((params, self.return_type()?)))
+            },
+            _ => return Ok(None),
+        };
+        let (params, return_type) = match head {
+            Ok(head) if self.eat_arrow() => head,
+            _ => {
+                self.rewind(checkpoint);
+                return Ok(None);
+            },
+        };
+        let body = match self.peek() {
+            Token::Lbrace => FunctionBody::Block(self.block()?),
+            _ => FunctionBody::Expr(Box::new(self.assignment()?)),
+        };
+        let function = Function {
+            id: self.ids.fresh(),
+            span: self.span_from(begin),
+            name: None,
+            params,
+            return_type,
+            throws: Vec::new(),
+            body,
+            arrow: true,
+        };
+        Ok(Some(self.expr(begin, ExprKind::Function(Box::new(function)))))
+    }
+
+    // Whether the parenthesis at the lookahead token closes before `=>`, or
+    // before a return type and `=>`. The parameters are skipped as tokens
+    // rather than parsed: a default value may hold another parenthesized
+    // expression, and parsing each of them both ways would take time
+    // exponential in the nesting.
+    fn arrow_params_ahead(&mut self) -> bool {
+        let checkpoint = self.checkpoint();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::Lparen | Token::Lbrack | Token::Lbrace => depth += 1,
+                Token::Rparen | Token::Rbrack | Token::Rbrace => depth -= 1,
+                Token::Eos => break,
+                _ => {},
+            }
+            self.bump();
+            if depth == 0 {
+                break;
+            }
+        }
+        let found = depth == 0 && match self.eat(&Token::Colon) {
+            true => self.parse_type().is_ok() && self.eat_arrow(),
+            false => self.eat_arrow(),
+        };
+        self.rewind(checkpoint);
+        found
+    }
+
+    pub(super) fn params(&mut self) -> Result<Vec<Param>, ParseError> {
+        self.expect(Token::Lparen)?;
+        let params = self.list(Token::Rparen, |parser| parser.param())?;
+        if let Some(param) = params.iter().rev().skip(1).find(|param| param.rest) {
+            return Err(self.error(param.span, "rest parameter must be last".to_string()));
+        }
+        Ok(params)
+    }
+
+    // `[...][Type] pattern [= default]`
+    fn param(&mut self) -> Result<Param, ParseError> {
+        let begin = self.peek_span().begin();
+        let rest = self.eat_ellipsis();
+        let ty = match self.type_ahead() {
+            true => Some(self.parse_type()?),
+            false => None,
+        };
+        let pattern = self.binding_pattern()?;
+        let default = match self.eat(&Token::Assign) {
+            true if rest => {
+                return Err(self.error(self.prev, "rest parameter cannot have a default value".to_string()));
+            },
+            true => Some(self.assignment()?),
+            false => None,
+        };
+        Ok(Param { id: self.ids.fresh(), span: self.span_from(begin), pattern, ty, default, rest })
+    }
+
+    pub(super) fn return_type(&mut self) -> Result<Option<Type>, ParseError> {
+        match self.eat(&Token::Colon) {
+            true => Ok(Some(self.parse_type()?)),
+            false => Ok(None),
+        }
+    }
+
+    // `...` is scanned as three periods, which must touch.
+    pub(super) fn eat_ellipsis(&mut self) -> bool {
+        if self.peek() != Token::Period {
+            return false;
+        }
+        let checkpoint = self.checkpoint();
+        let begin = self.peek_span().begin();
+        self.bump();
+        for _ in 0..2 {
+            if self.peek() != Token::Period || self.peek_span().begin() != self.prev.end() {
+                self.rewind(checkpoint);
+                return false;
+            }
+            self.bump();
+        }
+        self.prev = Span::new(begin, self.prev.end());
+        true
+    }
+
+    // Whether a type followed by a name, or a private name, starts at the
+    // lookahead token.
+    pub(super) fn type_ahead(&mut self) -> bool {
+        let checkpoint = self.checkpoint();
+        let found = self.parse_type().is_ok() && matches!(self.peek(), Token::Identifier(_) | Token::PrivateName(_));
+        self.rewind(checkpoint);
+        found
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use lex::scanner::Span;
+    use crate::ast::*;
+    use crate::parser::{parse_expression, parse_module};
+    use crate::parser::sexp::{error, expr, parse};
+
+    #[test]
+    fn parses_function_declarations() {
+        assert_eq!(
+            parse("def name(a, b = 1, ...rest) { return a }"),
+            "(def name (a b=1 ...rest) {(return a)})"
+        );
+        assert_eq!(
+            parse("def f(int x, ...List<T> xs): bool throws IOError, E {}"),
+            "(def f (int x ...List<T> xs) : bool throws IOError E {})"
+        );
+        assert_eq!(parse("def g() {} g()"), "(def g () {}) (call g)");
+        assert_eq!(error("def () {}"), (4, 5, "expected identifier, found `(`".to_string()));
+        assert_eq!(error("def f(...a, b) {}"), (6, 10, "rest parameter must be last".to_string()));
+        assert_eq!(error("def f(...a = 1) {}"), (11, 12, "rest parameter cannot have a default value".to_string()));
+    }
+
+    #[test]
+    fn records_parameter_spans() {
+        let module = parse_module("def f(a, int b = 2, ...c) {}").unwrap();
+        let function = match &module.body[0].kind {
+            StmtKind::Decl(Decl { kind: DeclKind::Function(function), .. }) => function,
+            kind => panic!("unexpected {:?}", kind),
+        };
+        let spans: Vec<Span> = function.params.iter().map(|param| param.span).collect();
+        assert_eq!(spans, vec![Span::new(6, 7), Span::new(9, 18), Span::new(20, 24)]);
+        assert_eq!(function.params[1].ty.as_ref().unwrap().span, Span::new(9, 12));
+        assert_eq!(function.span, Span::new(0, 28));
+    }
+
+    #[test]
+    fn parses_function_expressions() {
+        assert_eq!(parse("var f = def (x) { x++ };"), "(var (f (def (x) {(x++)})))");
+        assert_eq!(expr("def fact(n): int { return n }(3)"), "(call (def fact (n) : int {(return n)}) 3)");
+    }
+
+    #[test]
+    fn parses_arrow_functions() {
+        assert_eq!(expr("x => x + 1"), "(=> (x) (+ x 1))");
+        assert_eq!(expr("(x) => x + 1"), "(=> (x) (+ x 1))");
+        assert_eq!(expr("() => {}"), "(=> () {})");
+        assert_eq!(expr("(int x, y = 2): int => { return x * y }"), "(=> (int x y=2) : int {(return (* x y))})");
+        assert_eq!(expr("a.map(x => x * 2, (...r) => r)"), "(call (. a map) (=> (x) (* x 2)) (=> (...r) r))");
+        assert_eq!(expr("f = x => y => x"), "(= f (=> (x) (=> (y) x)))");
+        assert_eq!(expr("a ? (b) : (c) => d"), "(? a (paren b) (=> (c) d))");
+        assert_eq!(expr("(a, b)"), "(paren (, a b))");
+        assert_eq!(parse_expression("(a) = > b").unwrap_err().message, "unexpected `>`");
+    }
+
+    #[test]
+    fn parses_nested_parentheses_in_linear_time() {
+        // Each level used to be parsed as parameters and then again as an
+        // expression, doubling the work; thirty levels would not finish.
+        let code = format!("{}a{}", "(a = ".repeat(30), ")".repeat(30));
+        assert_eq!(expr(&code), format!("{}a{}", "(paren (= a ".repeat(30), "))".repeat(30)));
+        let code = format!("{}a{}", "c ? (a = ".repeat(30), ") : b".repeat(30));
+        assert!(parse_expression(&code).is_ok());
+        let code = format!("{}a{}", "(a = ".repeat(30), ") => a".repeat(30));
+        assert!(parse_expression(&code).is_ok());
+    }
+}
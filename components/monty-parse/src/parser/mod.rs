@@ -0,0 +1,466 @@
+// Recursive descent parser building the ast module's tree from the tokens
+// of a lex::scanner::Scanner. The grammar is split by area over the child
+// modules, which all add methods to Parser.
+//
+// Lists of statements and class members recover from syntax errors: the
+// error is recorded, the input skipped to where the next item likely
+// starts and an error node left in the tree. A missing expression or
+// semicolon is recorded and parsing goes on as if it were there.
+
+use lex::diagnostic::{Applicability, Diagnostic, Suggestion};
+use lex::scanner::{self, Scanner, Span};
+use lex::token::Token;
+use crate::ast::*;
+
+mod decl;
+mod expr;
+mod function;
+mod module;
+mod pattern;
+mod stmt;
+mod types;
+#[cfg(test)]
+mod sexp;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub location: Span,
+    pub message: String,
+    // A fix, such as inserting a missing ')'.
+    pub suggestion: Option<Suggestion>,
+}
+
+impl From<ParseError> for Diagnostic {
+
+    fn from(error: ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(error.message).with_primary(error.location, "");
+        match error.suggestion {
+            Some(suggestion) => diagnostic.with_suggestion(suggestion),
+            None => diagnostic,
+        }
+    }
+
+}
+
+pub struct Parser<'a> {
+    scanner: Scanner<'a>,
+    ids: NodeIds,
+    // Location of the last token consumed.
+    prev: Span,
+    // Whether a line terminator comes before the lookahead token.
+    newline: bool,
+    // Set while `in` ends an expression rather than being an operator, in
+    // the first clause of a for statement.
+    no_in: bool,
+    // The rest of a token that was split in two, as when the `>>` closing
+    // `List<List<int>>` is taken one `>` at a time. It comes before the
+    // scanner's lookahead, and the flag is the line break after it.
+    pending: Option<(Token, Span, bool)>,
+    // Number of '{' consumed and not yet closed.
+    depth: u32,
+    // Errors recovered from so far.
+    errors: Vec<ParseError>,
+}
+
+// Everything needed to backtrack after parsing ahead.
+struct Checkpoint {
+    scanner: scanner::Checkpoint,
+    ids: NodeIds,
+    prev: Span,
+    newline: bool,
+    pending: Option<(Token, Span, bool)>,
+    depth: u32,
+    errors: usize,
+}
+
+impl<'a> Parser<'a> {
+
+    pub fn new(mut scanner: Scanner<'a>) -> Parser<'a> {
+        scanner.init();
+        let begin = scanner.peek_location().begin();
+        let mut parser = Parser {
+            scanner,
+            ids: NodeIds::new(),
+            prev: Span::new(begin, begin),
+            newline: false,
+            no_in: false,
+            pending: None,
+            depth: 0,
+            errors: Vec::new(),
+        };
+        parser.skip_comments();
+        parser
+    }
+
+    pub fn scanner(&self) -> &Scanner<'a> {
+        &self.scanner
+    }
+
+    // The errors recovered from, in the order they were found.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    // Fails unless all input has been consumed.
+    pub fn expect_end(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Token::Eos => Ok(()),
+            token => Err(self.unexpected(token, self.peek_span())),
+        }
+    }
+
+    // Comma separated items up to and including `close`; a trailing comma
+    // is allowed.
+    fn list<T>(
+        &mut self,
+        close: Token,
+        mut item: impl FnMut(&mut Parser<'a>) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        self.with_in(true, |parser| {
+            let mut items = Vec::new();
+            while !parser.eat(&close) {
+                items.push(item(parser)?);
+                if parser.peek() != close {
+                    parser.expect(Token::Comma)?;
+                }
+            }
+            Ok(items)
+        })
+    }
+
+    // Runs `f` with `in` allowed as an operator or not.
+    fn with_in<T>(&mut self, allowed: bool, f: impl FnOnce(&mut Parser<'a>) -> T) -> T {
+        let no_in = std::mem::replace(&mut self.no_in, !allowed);
+        let result = f(self);
+        self.no_in = no_in;
+        result
+    }
+
+    fn binding_identifier(&mut self) -> Result<Identifier, ParseError> {
+        match self.peek() {
+            Token::Identifier(name) => {
+                self.bump();
+                Ok(self.identifier(name))
+            },
+            token => Err(self.error(self.peek_span(), format!("expected identifier, found {}", describe(&token)))),
+        }
+    }
+
+    /* Node construction. */
+
+    // An identifier for the token just consumed.
+    fn identifier(&mut self, name: Vec<char>) -> Identifier {
+        Identifier { id: self.ids.fresh(), span: self.prev, name: name.into_iter().collect() }
+    }
+
+    fn expr(&mut self, begin: u32, kind: ExprKind) -> Expr {
+        Expr { id: self.ids.fresh(), span: self.span_from(begin), kind }
+    }
+
+    // From `begin` to the end of the last token consumed.
+    fn span_from(&self, begin: u32) -> Span {
+        Span::new(begin, self.prev.end())
+    }
+
+    /* Tokens. */
+
+    fn peek(&self) -> Token {
+        match &self.pending {
+            Some((token, _, _)) => token.clone(),
+            None => self.scanner.peek(),
+        }
+    }
+
+    fn peek_span(&self) -> Span {
+        match &self.pending {
+            Some((_, span, _)) => *span,
+            None => self.scanner.peek_location(),
+        }
+    }
+
+    fn bump(&mut self) -> Token {
+        if let Some((token, span, newline)) = self.pending.take() {
+            self.prev = span;
+            self.newline = newline;
+            return token;
+        }
+        let token = self.scanner.next();
+        self.prev = self.scanner.location();
+        self.skip_comments();
+        match token {
+            Token::Lbrace => self.depth += 1,
+            Token::Rbrace => self.depth = self.depth.saturating_sub(1),
+            _ => {},
+        }
+        token
+    }
+
+    // Comments only reach the parser when the scanner Config asks for them.
+    fn skip_comments(&mut self) {
+        self.newline = self.scanner.has_line_terminator_before_next();
+        while let Token::Comment(_) = self.scanner.peek() {
+            self.scanner.next();
+            self.newline |= self.scanner.has_line_terminator_before_next();
+        }
+    }
+
+    // Consumes the first `width` bytes of the lookahead token as `first`,
+    // leaving the rest as `rest`.
+    fn split(&mut self, width: u32, first: Token, rest: Token) -> Token {
+        let span = self.peek_span();
+        self.bump();
+        let middle = span.begin() + width;
+        self.pending = Some((rest, Span::new(middle, span.end()), self.newline));
+        self.prev = Span::new(span.begin(), middle);
+        self.newline = false;
+        first
+    }
+
+    // `=>` is scanned as `=` and `>`, so it is recognized by the two tokens
+    // touching.
+    fn eat_arrow(&mut self) -> bool {
+        if self.peek() != Token::Assign {
+            return false;
+        }
+        let checkpoint = self.checkpoint();
+        let begin = self.peek_span().begin();
+        self.bump();
+        if self.peek() == Token::Gt && self.peek_span().begin() == self.prev.end() {
+            self.bump();
+            self.prev = Span::new(begin, self.prev.end());
+            return true;
+        }
+        self.rewind(checkpoint);
+        false
+    }
+
+    fn expect_arrow(&mut self) -> Result<Span, ParseError> {
+        if self.eat_arrow() {
+            return Ok(self.prev);
+        }
+        let found = self.peek();
+        Err(self.error(self.peek_span(), format!("expected `=>`, found {}", describe(&found))))
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            scanner: self.scanner.checkpoint(),
+            ids: self.ids.clone(),
+            prev: self.prev,
+            newline: self.newline,
+            pending: self.pending.clone(),
+            depth: self.depth,
+            errors: self.errors.len(),
+        }
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.scanner.rewind(checkpoint.scanner);
+        self.ids = checkpoint.ids;
+        self.prev = checkpoint.prev;
+        self.newline = checkpoint.newline;
+        self.pending = checkpoint.pending;
+        self.depth = checkpoint.depth;
+        self.errors.truncate(checkpoint.errors);
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == *token {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token) -> Result<Span, ParseError> {
+        let found = self.peek();
+        if found == token {
+            self.bump();
+            return Ok(self.prev);
+        }
+        if found == Token::Illegal {
+            return Err(self.unexpected(found, self.peek_span()));
+        }
+        let mut error = self.error(
+            self.peek_span(),
+            format!("expected {}, found {}", describe(&token), describe(&found)),
+        );
+        // A missing semicolon or closing bracket most likely belongs right
+        // after the last token. A semicolon is only certain to be right
+        // when what follows starts a statement of its own.
+        let applicability = match &token {
+            Token::Semicolon if starts_item(&found, self.newline) => Some(Applicability::MachineApplicable),
+            Token::Semicolon | Token::Rparen | Token::Rbrack | Token::Rbrace => Some(Applicability::MaybeIncorrect),
+            _ => None,
+        };
+        if let Some(applicability) = applicability {
+            let spelling = token.value();
+            let message = format!("insert `{}`", spelling);
+            error.suggestion = Some(Suggestion::insert(message, self.prev.end(), spelling, applicability));
+        }
+        Err(error)
+    }
+
+    /* Errors. */
+
+    // Parses an item of a list, such as a statement, with `parse`. After an
+    // error, what is left of the item is skipped and `error` makes the node
+    // taking its place. Either way some input is consumed, so loops over
+    // items end.
+    fn recovering<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser<'a>) -> Result<T, ParseError>,
+        error: impl FnOnce(&mut Parser<'a>, u32) -> T,
+    ) -> T {
+        let begin = self.peek_span().begin();
+        let (depth, errors) = (self.depth, self.errors.len());
+        let item = match parse(self) {
+            Ok(item) => Some(item),
+            Err(failure) => {
+                self.report(failure);
+                self.synchronize(depth);
+                None
+            },
+        };
+        // Only a token nothing can start, such as a stray '}', is left where
+        // it was. It is skipped with just the first error about it kept.
+        if self.peek_span().begin() == begin && self.peek() != Token::Eos {
+            self.errors.truncate(errors + 1);
+            self.bump();
+            return error(self, begin);
+        }
+        match item {
+            Some(item) => item,
+            None => error(self, begin),
+        }
+    }
+
+    // Skips what is left of an item that failed to parse, up to where the
+    // next item likely starts: after a ';', before a '}' or before a
+    // statement keyword, all at the `depth` the item started at. A '}'
+    // closing a block the item opened ends it too, with any ';' after it.
+    fn synchronize(&mut self, depth: u32) {
+        loop {
+            let token = self.peek();
+            if token == Token::Eos {
+                return;
+            }
+            if self.depth <= depth {
+                match token {
+                    Token::Semicolon => {
+                        self.bump();
+                        return;
+                    },
+                    Token::Rbrace => return,
+                    _ if starts_item(&token, self.newline) => return,
+                    _ => {},
+                }
+            }
+            self.bump();
+            if token == Token::Rbrace && self.depth <= depth {
+                self.eat(&Token::Semicolon);
+                return;
+            }
+        }
+    }
+
+    // Records an error recovered from. One that starts where the last one
+    // did is a consequence of it and dropped.
+    fn report(&mut self, error: ParseError) {
+        match self.errors.last() {
+            Some(last) if last.location.begin() == error.location.begin() => {},
+            _ => self.errors.push(error),
+        }
+    }
+
+    fn error(&self, location: Span, message: String) -> ParseError {
+        ParseError { location, message, suggestion: None }
+    }
+
+    fn unexpected(&self, token: Token, location: Span) -> ParseError {
+        // The scanner has already said what is wrong with an illegal token.
+        if token == Token::Illegal {
+            if let Some(error) = self.scanner.errors().iter().rev().find(|error| error.location.begin() == location.begin()) {
+                return self.error(error.location, error.message.clone());
+            }
+        }
+        self.error(location, format!("unexpected {}", describe(&token)))
+    }
+
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Eos => "end of input".to_string(),
+        Token::Identifier(_) => format!("identifier `{}`", token.clone().value()),
+        Token::Number(_) => format!("number `{}`", token.clone().value()),
+        Token::String(_) | Token::CharLiteral(_) => "string literal".to_string(),
+        Token::CustomOperator(_) => "operator".to_string(),
+        _ => format!("`{}`", token.clone().value()),
+    }
+}
+
+// Whether `token` starts a statement or class member, so that skipping
+// after an error stops there. `def`, annotations and modifiers only count
+// at the start of a line, since `def` also starts function expressions.
+fn starts_item(token: &Token, newline: bool) -> bool {
+    match token {
+        Token::If
+        | Token::While
+        | Token::Do
+        | Token::For
+        | Token::Switch
+        | Token::Break
+        | Token::Continue
+        | Token::Return
+        | Token::Throw
+        | Token::Try
+        | Token::With
+        | Token::Var
+        | Token::Const
+        | Token::Class
+        | Token::Interface
+        | Token::Enum
+        | Token::Package
+        | Token::Import
+        | Token::Export => true,
+        Token::Function
+        | Token::At
+        | Token::Public
+        | Token::Protected
+        | Token::Private
+        | Token::Static
+        | Token::Abstract
+        | Token::Native
+        | Token::Final => newline,
+        _ => false,
+    }
+}
+
+// Parses `code` as a single expression.
+pub fn parse_expression(code: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(Scanner::new(code, 0));
+    let expr = parser.expression().and_then(|expr| parser.expect_end().map(|_| expr));
+    match parser.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => expr,
+    }
+}
+
+// Parses `code` as a sequence of statements, failing with the first
+// syntax error.
+pub fn parse_module(code: &str) -> Result<Module, ParseError> {
+    let (module, errors) = parse_module_recovering(code);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(module),
+    }
+}
+
+// Parses `code` as a sequence of statements, going on after syntax errors.
+// The tree has error nodes where they were.
+pub fn parse_module_recovering(code: &str) -> (Module, Vec<ParseError>) {
+    let mut parser = Parser::new(Scanner::new(code, 0));
+    let module = parser.module();
+    (module, parser.errors)
+}
@@ -0,0 +1,194 @@
+// Module syntax: `package a.b;`, imports and exports. These may only appear
+// at the top level of a module, and a package declaration must come first.
+// `as` and `from` are not keywords; they are only special here.
+
+use lex::token::Token;
+use crate::ast::*;
+use super::{describe, ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // A package, import or export declaration, starting at the lookahead
+    // token. `first` tells whether it is the first item of the module.
+    pub(super) fn module_declaration(&mut self, first: bool) -> Result<Stmt, ParseError> {
+        let begin = self.peek_span().begin();
+        let decl = match self.peek() {
+            Token::Package => {
+                self.bump();
+                if !first {
+                    return Err(self.error(self.prev, "package declaration must come first".to_string()));
+                }
+                let path = self.path()?;
+                self.decl(begin, DeclKind::Package(path))
+            },
+            Token::Import => {
+                let import = self.import()?;
+                self.decl(begin, DeclKind::Import(import))
+            },
+            _ => {
+                let export = self.export()?;
+                self.decl(begin, DeclKind::Export(export))
+            },
+        };
+        // Exported functions, classes and the like end with their body.
+        let terminated = match &decl.kind {
+            DeclKind::Export(Export { kind: ExportKind::Decl(decl), .. }) => !matches!(decl.kind, DeclKind::Var(_)),
+            _ => false,
+        };
+        if !terminated {
+            self.semicolon()?;
+        }
+        Ok(self.stmt(begin, StmtKind::Decl(decl)))
+    }
+
+    // `import a.b.C` or `import { x, y as z } from "path"`
+    fn import(&mut self) -> Result<Import, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Import)?;
+        let kind = match self.peek() {
+            Token::Lbrace => {
+                let names = self.import_names()?;
+                self.expect_contextual("from")?;
+                ImportKind::Named { names, from: self.string_literal()? }
+            },
+            _ => ImportKind::Path(self.path()?),
+        };
+        Ok(Import { id: self.ids.fresh(), span: self.span_from(begin), kind })
+    }
+
+    // `export { x, y as z }`, `export { x } from "path"`, `export * from
+    // "path"` or `export` followed by a declaration.
+    fn export(&mut self) -> Result<Export, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Export)?;
+        let kind = match self.peek() {
+            Token::Lbrace => {
+                let names = self.import_names()?;
+                let from = match self.eat_contextual("from") {
+                    true => Some(self.string_literal()?),
+                    false => None,
+                };
+                ExportKind::Named { names, from }
+            },
+            Token::Mul => {
+                self.bump();
+                self.expect_contextual("from")?;
+                ExportKind::All(self.string_literal()?)
+            },
+            Token::Package | Token::Import | Token::Export => {
+                let token = self.peek();
+                return Err(self.error(self.peek_span(), format!("expected declaration, found {}", describe(&token))));
+            },
+            _ if self.at_var_declaration() && self.peek() != Token::Final => {
+                let decl_begin = self.peek_span().begin();
+                let decl = self.var_declaration()?;
+                ExportKind::Decl(Box::new(self.decl(decl_begin, DeclKind::Var(decl))))
+            },
+            _ => ExportKind::Decl(Box::new(self.declaration()?)),
+        };
+        Ok(Export { id: self.ids.fresh(), span: self.span_from(begin), kind })
+    }
+
+    // `{ x, y as z }`
+    fn import_names(&mut self) -> Result<Vec<ImportName>, ParseError> {
+        self.expect(Token::Lbrace)?;
+        self.list(Token::Rbrace, |parser| {
+            let begin = parser.peek_span().begin();
+            let name = parser.binding_identifier()?;
+            let alias = match parser.eat_contextual("as") {
+                true => Some(parser.binding_identifier()?),
+                false => None,
+            };
+            Ok(ImportName { id: parser.ids.fresh(), span: parser.span_from(begin), name, alias })
+        })
+    }
+
+    fn string_literal(&mut self) -> Result<StringLiteral, ParseError> {
+        match self.bump() {
+            Token::String(value) => Ok(StringLiteral { id: self.ids.fresh(), span: self.prev, value: value.into_iter().collect() }),
+            token => Err(self.error(self.prev, format!("expected string literal, found {}", describe(&token)))),
+        }
+    }
+
+    // Consumes an identifier spelled `word`, if one is next.
+    fn eat_contextual(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Token::Identifier(name) if name.iter().copied().eq(word.chars()) => {
+                self.bump();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn expect_contextual(&mut self, word: &str) -> Result<(), ParseError> {
+        if self.eat_contextual(word) {
+            return Ok(());
+        }
+        let token = self.peek();
+        Err(self.error(self.peek_span(), format!("expected `{}`, found {}", word, describe(&token))))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use lex::scanner::Span;
+    use crate::ast::*;
+    use crate::parser::parse_module;
+    use crate::parser::sexp::{error, parse};
+
+    #[test]
+    fn parses_packages_and_imports() {
+        assert_eq!(
+            parse("package a.b;\nimport a.b.C\nimport { x, y as z, } from \"./util\";"),
+            "(package a.b) (import a.b.C) (import {x (y z)} from './util')"
+        );
+        assert_eq!(error("import {x} 'p'"), (11, 14, "expected `from`, found string literal".to_string()));
+        assert_eq!(error("import {x} from y"), (16, 17, "expected string literal, found identifier `y`".to_string()));
+        assert_eq!(error("x; package a"), (3, 10, "package declaration must come first".to_string()));
+        assert_eq!(error("package a; package b"), (11, 18, "package declaration must come first".to_string()));
+    }
+
+    #[test]
+    fn parses_exports() {
+        assert_eq!(
+            parse("export def f() {} export class C {} export int x = 1, y; export final n = 2"),
+            "(export (def f () {})) (export (class C {})) (export (int (x 1) (y))) (export (final (n 2)))"
+        );
+        assert_eq!(
+            parse("export { a, b as c }; export { d } from 'm'\nexport * from 'n'"),
+            "(export {a (b c)}) (export {d} from 'm') (export * from 'n')"
+        );
+        assert_eq!(parse("export public abstract class A {}"), "(export (public abstract (class A {})))");
+        assert_eq!(error("export import a"), (7, 13, "expected declaration, found `import`".to_string()));
+        assert_eq!(error("export * 'n'"), (9, 12, "expected `from`, found string literal".to_string()));
+    }
+
+    #[test]
+    fn is_only_allowed_at_top_level() {
+        assert_eq!(error("{ import a }"), (2, 8, "`import` is only allowed at the top level of a module".to_string()));
+        assert_eq!(
+            error("def f() { export def g() {} }"),
+            (10, 16, "`export` is only allowed at the top level of a module".to_string())
+        );
+    }
+
+    #[test]
+    fn records_module_spans() {
+        let module = parse_module("import {x as y} from 'z';").unwrap();
+        assert_eq!(module.body[0].span, Span::new(0, 25));
+        let import = match &module.body[0].kind {
+            StmtKind::Decl(Decl { kind: DeclKind::Import(import), .. }) => import,
+            kind => panic!("unexpected {:?}", kind),
+        };
+        assert_eq!(import.span, Span::new(0, 24));
+        match &import.kind {
+            ImportKind::Named { names, from } => {
+                assert_eq!(names[0].span, Span::new(8, 14));
+                assert_eq!(from.span, Span::new(21, 24));
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+}
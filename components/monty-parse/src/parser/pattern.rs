@@ -0,0 +1,194 @@
+// Destructuring patterns. Declarations and parameters parse them directly.
+// An assignment only knows it has one when it reaches the '=', so its
+// target is first parsed as an array or object literal and then
+// reinterpreted as a pattern.
+
+use lex::token::Token;
+use crate::ast::*;
+use super::{ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // `name`, `[a, b = 1, ...rest]` or `{a, b: c = 1}`
+    pub(super) fn binding_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let begin = self.peek_span().begin();
+        let kind = match self.peek() {
+            Token::Lbrack => {
+                self.bump();
+                let elements = self.list(Token::Rbrack, |parser| {
+                    let begin = parser.peek_span().begin();
+                    let rest = parser.eat_ellipsis();
+                    let pattern = parser.binding_pattern()?;
+                    let default = parser.pattern_default()?;
+                    Ok(PatternElement { id: parser.ids.fresh(), span: parser.span_from(begin), pattern, default, rest })
+                })?;
+                self.check_elements(&elements)?;
+                PatternKind::Array(elements)
+            },
+            Token::Lbrace => {
+                self.bump();
+                PatternKind::Object(self.list(Token::Rbrace, |parser| {
+                    let begin = parser.peek_span().begin();
+                    let shorthand = matches!(parser.peek(), Token::Identifier(_));
+                    let key = parser.property_key()?;
+                    let value = match &key {
+                        PropertyKey::Identifier(name) if shorthand && parser.peek() != Token::Colon => {
+                            let name = Identifier { id: parser.ids.fresh(), span: name.span, name: name.name.clone() };
+                            Pattern { id: parser.ids.fresh(), span: name.span, kind: PatternKind::Identifier(name) }
+                        },
+                        _ => {
+                            parser.expect(Token::Colon)?;
+                            parser.binding_pattern()?
+                        },
+                    };
+                    let default = parser.pattern_default()?;
+                    Ok(PatternProperty { id: parser.ids.fresh(), span: parser.span_from(begin), key, value, default })
+                })?)
+            },
+            _ => PatternKind::Identifier(self.binding_identifier()?),
+        };
+        Ok(Pattern { id: self.ids.fresh(), span: self.span_from(begin), kind })
+    }
+
+    fn pattern_default(&mut self) -> Result<Option<Expr>, ParseError> {
+        match self.eat(&Token::Assign) {
+            true => Ok(Some(self.assignment()?)),
+            false => Ok(None),
+        }
+    }
+
+    // The target of an assignment, given what was parsed as its left-hand
+    // side. Array and object literals become patterns, with `x = value`
+    // items read as defaults.
+    pub(super) fn assignment_target(&self, expr: Expr) -> Result<Pattern, ParseError> {
+        let (id, span) = (expr.id, expr.span);
+        let kind = match expr.kind {
+            ExprKind::Identifier(name) => PatternKind::Identifier(name),
+            ExprKind::Array(items) => {
+                let mut elements = Vec::new();
+                for item in items {
+                    let (id, span) = (item.id, item.span);
+                    let (item, rest) = match item.kind {
+                        ExprKind::Spread(operand) => (*operand, true),
+                        kind => (Expr { id, span, kind }, false),
+                    };
+                    let (pattern, default) = self.target_with_default(item)?;
+                    elements.push(PatternElement { id, span, pattern, default, rest });
+                }
+                self.check_elements(&elements)?;
+                PatternKind::Array(elements)
+            },
+            ExprKind::Object(properties) => {
+                let mut patterns = Vec::new();
+                for property in properties {
+                    let (value, default) = self.target_with_default(property.value)?;
+                    patterns.push(PatternProperty {
+                        id: property.id,
+                        span: property.span,
+                        key: property.key,
+                        value,
+                        default,
+                    });
+                }
+                PatternKind::Object(patterns)
+            },
+            kind => {
+                let expr = Expr { id, span, kind };
+                self.check_target(&expr)?;
+                PatternKind::Expr(Box::new(expr))
+            },
+        };
+        Ok(Pattern { id, span, kind })
+    }
+
+    // An item of an array or object literal that is becoming a pattern.
+    // `x = value` was parsed as an assignment, whose target already is a
+    // pattern.
+    fn target_with_default(&self, item: Expr) -> Result<(Pattern, Option<Expr>), ParseError> {
+        match item.kind {
+            ExprKind::Assign { op: Token::Assign, target, value } => Ok((*target, Some(*value))),
+            kind => Ok((self.assignment_target(Expr { id: item.id, span: item.span, kind })?, None)),
+        }
+    }
+
+    fn check_elements(&self, elements: &[PatternElement]) -> Result<(), ParseError> {
+        if let Some(element) = elements.iter().rev().skip(1).find(|element| element.rest) {
+            return Err(self.error(element.span, "rest element must be last".to_string()));
+        }
+        match elements.iter().find(|element| element.rest && element.default.is_some()) {
+            Some(element) => Err(self.error(element.span, "rest element cannot have a default value".to_string())),
+            None => Ok(()),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use lex::scanner::Span;
+    use crate::ast::*;
+    use crate::parser::parse_expression;
+    use crate::parser::sexp::{error, expr, parse};
+
+    #[test]
+    fn parses_declaration_patterns() {
+        assert_eq!(parse("var [a, b, ...rest] = xs;"), "(var ([a b ...rest] xs))");
+        assert_eq!(parse("var {name, age: years = 0} = person;"), "(var ({name age: years=0} person))");
+        assert_eq!(
+            parse("const {a: [b, {c}], 'd': e = 1, 2: f, if: g} = o"),
+            "(const ({a: [b {c}] 'd': e=1 2: f if: g} o))"
+        );
+        assert_eq!(parse("final [x = 1, [y]] = z, w = 2"), "(final ([x=1 [y]] z) (w 2))");
+        assert_eq!(error("var [a, ...b, c] = d"), (8, 12, "rest element must be last".to_string()));
+        assert_eq!(error("var [...b = 1] = d"), (5, 13, "rest element cannot have a default value".to_string()));
+        assert_eq!(error("var {if} = o"), (7, 8, "expected `:`, found `}`".to_string()));
+        assert_eq!(error("var [a] ;"), (4, 7, "missing initializer in destructuring declaration".to_string()));
+    }
+
+    #[test]
+    fn parses_parameter_and_for_in_patterns() {
+        assert_eq!(
+            parse("def f([a, b], {c = 1} = {}, ...[d]) {}"),
+            "(def f ([a b] {c=1}={} ...[d]) {})"
+        );
+        assert_eq!(expr("([k, v]) => k"), "(=> ([k v]) k)");
+        assert_eq!(expr("({x, y}) => x"), "(=> ({x y}) x)");
+        assert_eq!(parse("for (var [k, v] in entries) {}"), "(for-in (var ([k v])) entries {})");
+        assert_eq!(parse("for ([k, v] in entries) {}"), "(for-in [k v] entries {})");
+        assert_eq!(parse("for ({key} in entries) {}"), "(for-in {key} entries {})");
+        assert_eq!(error("for ([k, 1] in entries) {}"), (9, 10, "invalid assignment target".to_string()));
+    }
+
+    #[test]
+    fn reinterprets_literals_as_assignment_targets() {
+        assert_eq!(expr("[a, b] = [b, a]"), "(= [a b] [b a])");
+        assert_eq!(expr("[a = 1, ...o.rest] = xs"), "(= [a=1 ...(. o rest)] xs)");
+        assert_eq!(expr("({name, age: years = 0} = person)"), "(paren (= {name age: years=0} person))");
+        assert_eq!(expr("[{a: [b]}, c[0]] = d"), "(= [{a: [b]} ([] c 0)] d)");
+        assert_eq!(expr("[a, b] = [c] = d"), "(= [a b] (= [c] d))");
+        assert_eq!(expr("x = [a, ...b]"), "(= x [a ...b])");
+        assert_eq!(expr("({a} = o).a"), "(. (paren (= {a} o)) a)");
+        assert_eq!(parse_expression("[a + 1] = b").unwrap_err().location, Span::new(1, 6));
+        assert_eq!(parse_expression("[...a, b] = c").unwrap_err().message, "rest element must be last");
+        assert_eq!(parse_expression("[a] += b").unwrap_err().message, "invalid assignment target");
+        assert_eq!(parse_expression("({a: 1} = o)").unwrap_err().location, Span::new(5, 6));
+    }
+
+    #[test]
+    fn records_pattern_spans() {
+        let expr = parse_expression("[a, ...b] = c").unwrap();
+        let target = match &expr.kind {
+            ExprKind::Assign { target, .. } => target,
+            kind => panic!("unexpected {:?}", kind),
+        };
+        assert_eq!(target.span, Span::new(0, 9));
+        match &target.kind {
+            PatternKind::Array(elements) => {
+                assert_eq!(elements[1].span, Span::new(4, 8));
+                assert_eq!(elements[1].pattern.span, Span::new(7, 8));
+                assert!(elements[1].rest);
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+}
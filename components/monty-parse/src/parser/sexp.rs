@@ -0,0 +1,396 @@
+// S-expression printing of trees, to keep test expectations readable.
+
+use lex::token::Token;
+use crate::ast::*;
+use crate::parser::{parse_expression, parse_module};
+
+// The statements of a module, printed.
+pub fn parse(code: &str) -> String {
+    let module = parse_module(code).unwrap();
+    let statements: Vec<String> = module.body.iter().map(stmt).collect();
+    statements.join(" ")
+}
+
+pub fn expr(code: &str) -> String {
+    sexp(&parse_expression(code).unwrap())
+}
+
+// Where a module fails to parse, and why.
+pub fn error(code: &str) -> (u32, u32, String) {
+    let error = parse_module(code).unwrap_err();
+    (error.location.begin(), error.location.end(), error.message)
+}
+
+pub fn expr_error(code: &str) -> (u32, u32, String) {
+    let error = parse_expression(code).unwrap_err();
+    (error.location.begin(), error.location.end(), error.message)
+}
+
+pub fn sexp(expr: &Expr) -> String {
+    let list = |head: String, items: Vec<&Expr>| {
+        let items: Vec<String> = items.into_iter().map(sexp).collect();
+        format!("({} {})", head, items.join(" "))
+    };
+    let op_name = |op: &Token| match op {
+        Token::CustomOperator(symbol) => format!("op{}", symbol.0),
+        _ => op.clone().value(),
+    };
+    match &expr.kind {
+        ExprKind::Number(digits) => digits.clone(),
+        ExprKind::String(value) => format!("'{}'", value),
+        ExprKind::Char(ch) => format!("c'{}'", ch),
+        ExprKind::Bool(value) => value.to_string(),
+        ExprKind::Null => "null".to_string(),
+        ExprKind::This => "this".to_string(),
+        ExprKind::Identifier(identifier) => identifier.name.clone(),
+        ExprKind::Paren(inner) => list("paren".to_string(), vec![inner]),
+        ExprKind::Unary { op, operand } => list(op_name(op), vec![operand]),
+        ExprKind::Update { op, prefix: true, operand } => format!("({}{})", op_name(op), sexp(operand)),
+        ExprKind::Update { op, prefix: false, operand } => format!("({}{})", sexp(operand), op_name(op)),
+        ExprKind::Binary { op, left, right } => list(op_name(op), vec![left, right]),
+        ExprKind::Assign { op, target, value } => format!("({} {} {})", op_name(op), pattern(target), sexp(value)),
+        ExprKind::Conditional { condition, then, otherwise } => {
+            list("?".to_string(), vec![condition, then, otherwise])
+        },
+        ExprKind::Sequence(exprs) => list(",".to_string(), exprs.iter().collect()),
+        ExprKind::Super => "super".to_string(),
+        ExprKind::Member { object, property } => {
+            let name = match property {
+                MemberName::Public(name) => name.name.clone(),
+                MemberName::Private(name) => format!("#{}", name.name),
+            };
+            format!("(. {} {})", sexp(object), name)
+        },
+        ExprKind::Index { object, index } => list("[]".to_string(), vec![object, index]),
+        ExprKind::Call { callee, arguments } => {
+            list("call".to_string(), std::iter::once(&**callee).chain(arguments).collect())
+        },
+        ExprKind::New { callee, arguments: None } => list("new".to_string(), vec![callee]),
+        ExprKind::New { callee, arguments: Some(arguments) } => {
+            let arguments: Vec<String> = arguments.iter().map(sexp).collect();
+            format!("(new {} ({}))", sexp(callee), arguments.join(" "))
+        },
+        ExprKind::Array(items) => {
+            let items: Vec<String> = items.iter().map(sexp).collect();
+            format!("[{}]", items.join(" "))
+        },
+        ExprKind::Spread(operand) => format!("...{}", sexp(operand)),
+        ExprKind::Object(properties) => {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|property| {
+                    let key = match &property.key {
+                        PropertyKey::Identifier(name) => name.name.clone(),
+                        PropertyKey::String(string) => format!("'{}'", string.value),
+                        PropertyKey::Number(digits) => digits.clone(),
+                    };
+                    format!("{}: {}", key, sexp(&property.value))
+                })
+                .collect();
+            format!("{{{}}}", properties.join(", "))
+        },
+        ExprKind::Function(function_) => function(function_),
+        ExprKind::Error => "(error)".to_string(),
+    }
+}
+
+pub fn stmt(stmt: &Stmt) -> String {
+    let optional = |expr: &Option<Expr>| expr.as_ref().map_or("_".to_string(), sexp);
+    match &stmt.kind {
+        StmtKind::Block(block_) => block(block_),
+        StmtKind::Expr(expr) => sexp(expr),
+        StmtKind::Empty => ";".to_string(),
+        StmtKind::If { condition, then, otherwise: None } => format!("(if {} {})", sexp(condition), self::stmt(then)),
+        StmtKind::If { condition, then, otherwise: Some(otherwise) } => {
+            format!("(if {} {} {})", sexp(condition), self::stmt(then), self::stmt(otherwise))
+        },
+        StmtKind::While { condition, body } => format!("(while {} {})", sexp(condition), self::stmt(body)),
+        StmtKind::DoWhile { body, condition } => format!("(do {} {})", self::stmt(body), sexp(condition)),
+        StmtKind::For { init, condition, update, body } => {
+            let init = init.as_ref().map_or("_".to_string(), for_init);
+            format!("(for {} {} {} {})", init, optional(condition), optional(update), self::stmt(body))
+        },
+        StmtKind::ForIn { target, object, body } => {
+            format!("(for-in {} {} {})", for_init(target), sexp(object), self::stmt(body))
+        },
+        StmtKind::Switch { discriminant, cases } => {
+            let cases: Vec<String> = cases
+                .iter()
+                .map(|case| {
+                    let head = case.test.as_ref().map_or("default".to_string(), |test| format!("case {}", sexp(test)));
+                    statements(head, &case.body)
+                })
+                .collect();
+            format!("(switch {} {})", sexp(discriminant), cases.join(" "))
+        },
+        StmtKind::Break(label) => jump("break", label),
+        StmtKind::Continue(label) => jump("continue", label),
+        StmtKind::Return(None) => "(return)".to_string(),
+        StmtKind::Return(Some(value)) => format!("(return {})", sexp(value)),
+        StmtKind::Throw(value) => format!("(throw {})", sexp(value)),
+        StmtKind::Try { block: try_block, catch, finally } => {
+            let mut out = format!("(try {}", block(try_block));
+            if let Some(catch) = catch {
+                match &catch.param {
+                    Some(param) => out.push_str(&format!(" (catch {} {})", param.name, block(&catch.body))),
+                    None => out.push_str(&format!(" (catch {})", block(&catch.body))),
+                }
+            }
+            if let Some(finally) = finally {
+                out.push_str(&format!(" (finally {})", block(finally)));
+            }
+            out + ")"
+        },
+        StmtKind::With { object, body } => format!("(with {} {})", sexp(object), self::stmt(body)),
+        StmtKind::Labelled { label, body } => format!("({}: {})", label.name, self::stmt(body)),
+        StmtKind::Decl(decl) => self::decl(decl),
+        StmtKind::Error => "(error)".to_string(),
+    }
+}
+
+fn block(block: &Block) -> String {
+    let body: Vec<String> = block.body.iter().map(stmt).collect();
+    format!("{{{}}}", body.join(" "))
+}
+
+fn statements(head: String, body: &[Stmt]) -> String {
+    let mut items = vec![head];
+    items.extend(body.iter().map(stmt));
+    format!("({})", items.join(" "))
+}
+
+fn jump(keyword: &str, label: &Option<Identifier>) -> String {
+    match label {
+        Some(label) => format!("({} {})", keyword, label.name),
+        None => format!("({})", keyword),
+    }
+}
+
+fn for_init(init: &ForInit) -> String {
+    match init {
+        ForInit::Expr(expr) => sexp(expr),
+        ForInit::Var(decl) => var_decl(decl),
+        ForInit::Pattern(target) => pattern(target),
+    }
+}
+
+pub fn decl(decl: &Decl) -> String {
+    let inner = match &decl.kind {
+        DeclKind::Var(decl) => var_decl(decl),
+        DeclKind::Function(function_) => function(function_),
+        DeclKind::Class(class) => {
+            let mut items = vec![format!("class {}", class.name.name)];
+            if let Some(extends) = &class.extends {
+                items.push(format!("extends {}", ty(extends)));
+            }
+            if !class.implements.is_empty() {
+                items.push("implements".to_string());
+                items.extend(class.implements.iter().map(ty));
+            }
+            let members: Vec<String> = class.members.iter().map(class_member).collect();
+            items.push(format!("{{{}}}", members.join(" ")));
+            format!("({})", items.join(" "))
+        },
+        DeclKind::Interface(interface) => {
+            let mut items = vec![format!("interface {}", interface.name.name)];
+            if !interface.extends.is_empty() {
+                items.push("extends".to_string());
+                items.extend(interface.extends.iter().map(ty));
+            }
+            let methods: Vec<String> = interface.methods.iter().map(function).collect();
+            items.push(format!("{{{}}}", methods.join(" ")));
+            format!("({})", items.join(" "))
+        },
+        DeclKind::Enum(enumeration) => {
+            let variants: Vec<String> = enumeration
+                .variants
+                .iter()
+                .map(|variant| match &variant.value {
+                    Some(value) => format!("({} {})", variant.name.name, sexp(value)),
+                    None => variant.name.name.clone(),
+                })
+                .collect();
+            format!("(enum {} {{{}}})", enumeration.name.name, variants.join(" "))
+        },
+        DeclKind::Package(name) => format!("(package {})", path(name)),
+        DeclKind::Import(import) => match &import.kind {
+            ImportKind::Path(name) => format!("(import {})", path(name)),
+            ImportKind::Named { names, from } => format!("(import {} from '{}')", import_names(names), from.value),
+        },
+        DeclKind::Export(export) => match &export.kind {
+            ExportKind::Decl(exported) => format!("(export {})", self::decl(exported)),
+            ExportKind::Named { names, from: None } => format!("(export {})", import_names(names)),
+            ExportKind::Named { names, from: Some(from) } => {
+                format!("(export {} from '{}')", import_names(names), from.value)
+            },
+            ExportKind::All(from) => format!("(export * from '{}')", from.value),
+        },
+    };
+    decorated(&decl.annotations, &decl.modifiers, inner)
+}
+
+fn import_names(names: &[ImportName]) -> String {
+    let names: Vec<String> = names
+        .iter()
+        .map(|name| match &name.alias {
+            Some(alias) => format!("({} {})", name.name.name, alias.name),
+            None => name.name.name.clone(),
+        })
+        .collect();
+    format!("{{{}}}", names.join(" "))
+}
+
+fn class_member(member: &ClassMember) -> String {
+    let inner = match &member.kind {
+        ClassMemberKind::Field { name, ty: field_type, init } => {
+            let mut items = vec!["field".to_string()];
+            items.extend(field_type.iter().map(ty));
+            items.push(member_name(name));
+            items.extend(init.iter().map(sexp));
+            format!("({})", items.join(" "))
+        },
+        ClassMemberKind::Method { name, function: method } => {
+            format!("(method {} {})", member_name(name), function(method))
+        },
+        ClassMemberKind::Constructor(constructor) => format!("(constructor {})", function(constructor)),
+        ClassMemberKind::Error => "(error)".to_string(),
+    };
+    decorated(&member.annotations, &member.modifiers, inner)
+}
+
+fn member_name(name: &MemberName) -> String {
+    match name {
+        MemberName::Public(name) => name.name.clone(),
+        MemberName::Private(name) => format!("#{}", name.name),
+    }
+}
+
+// Prefixes annotations and modifiers, if there are any.
+fn decorated(annotations: &[Annotation], modifiers: &[Modifier], inner: String) -> String {
+    if annotations.is_empty() && modifiers.is_empty() {
+        return inner;
+    }
+    let mut items: Vec<String> = annotations
+        .iter()
+        .map(|annotation| match &annotation.arguments {
+            Some(arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(sexp).collect();
+                format!("@{}({})", path(&annotation.name), arguments.join(" "))
+            },
+            None => format!("@{}", path(&annotation.name)),
+        })
+        .collect();
+    items.extend(modifiers.iter().map(|modifier| format!("{:?}", modifier).to_lowercase()));
+    items.push(inner);
+    format!("({})", items.join(" "))
+}
+
+fn var_decl(decl: &VarDecl) -> String {
+    let mut items = Vec::new();
+    match decl.kind {
+        VarKind::Var => items.push("var".to_string()),
+        VarKind::Const => items.push("const".to_string()),
+        VarKind::Final => items.push("final".to_string()),
+        VarKind::Typed => {},
+    }
+    items.extend(decl.ty.iter().map(ty));
+    for declarator in &decl.declarators {
+        match &declarator.init {
+            Some(init) => items.push(format!("({} {})", pattern(&declarator.pattern), sexp(init))),
+            None => items.push(format!("({})", pattern(&declarator.pattern))),
+        }
+    }
+    format!("({})", items.join(" "))
+}
+
+// Types print as they are written.
+pub fn ty(ty: &Type) -> String {
+    let list = |types: &[Type]| types.iter().map(self::ty).collect::<Vec<String>>().join(", ");
+    match &ty.kind {
+        TypeKind::Primitive(primitive) => format!("{:?}", primitive).to_lowercase().replace("boolean", "bool"),
+        TypeKind::Named(name) => path(name),
+        TypeKind::Generic { base, arguments } => format!("{}<{}>", path(base), list(arguments)),
+        TypeKind::Array(element) => format!("{}[]", self::ty(element)),
+        TypeKind::Nullable(inner) => format!("{}?", self::ty(inner)),
+        TypeKind::Function { params, result } => format!("({}) => {}", list(params), self::ty(result)),
+    }
+}
+
+pub fn path(path: &Path) -> String {
+    path.segments.iter().map(|segment| segment.name.as_str()).collect::<Vec<&str>>().join(".")
+}
+
+pub fn function(function: &Function) -> String {
+    let mut items = vec![if function.arrow { "=>" } else { "def" }.to_string()];
+    items.extend(function.name.iter().map(|name| name.name.clone()));
+    let params: Vec<String> = function.params.iter().map(param).collect();
+    items.push(format!("({})", params.join(" ")));
+    if let Some(return_type) = &function.return_type {
+        items.push(format!(": {}", ty(return_type)));
+    }
+    if !function.throws.is_empty() {
+        items.push("throws".to_string());
+        items.extend(function.throws.iter().map(ty));
+    }
+    match &function.body {
+        FunctionBody::Block(body) => items.push(block(body)),
+        FunctionBody::Expr(body) => items.push(sexp(body)),
+        FunctionBody::None => {},
+    }
+    format!("({})", items.join(" "))
+}
+
+fn param(param: &Param) -> String {
+    let mut out = String::new();
+    if param.rest {
+        out.push_str("...");
+    }
+    if let Some(param_type) = &param.ty {
+        out.push_str(&ty(param_type));
+        out.push(' ');
+    }
+    out.push_str(&pattern(&param.pattern));
+    if let Some(default) = &param.default {
+        out.push('=');
+        out.push_str(&sexp(default));
+    }
+    out
+}
+
+// Patterns print as they are written, without commas.
+pub fn pattern(pattern: &Pattern) -> String {
+    let default = |default: &Option<Expr>| default.as_ref().map_or(String::new(), |default| format!("={}", sexp(default)));
+    match &pattern.kind {
+        PatternKind::Identifier(name) => name.name.clone(),
+        PatternKind::Array(elements) => {
+            let elements: Vec<String> = elements
+                .iter()
+                .map(|element| {
+                    let rest = if element.rest { "..." } else { "" };
+                    format!("{}{}{}", rest, self::pattern(&element.pattern), default(&element.default))
+                })
+                .collect();
+            format!("[{}]", elements.join(" "))
+        },
+        PatternKind::Object(properties) => {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|property| {
+                    let value = self::pattern(&property.value);
+                    let key = match &property.key {
+                        PropertyKey::Identifier(name) if name.name == value => None,
+                        PropertyKey::Identifier(name) => Some(name.name.clone()),
+                        PropertyKey::String(string) => Some(format!("'{}'", string.value)),
+                        PropertyKey::Number(digits) => Some(digits.clone()),
+                    };
+                    match key {
+                        Some(key) => format!("{}: {}{}", key, value, default(&property.default)),
+                        None => format!("{}{}", value, default(&property.default)),
+                    }
+                })
+                .collect();
+            format!("{{{}}}", properties.join(" "))
+        },
+        PatternKind::Expr(expr) => sexp(expr),
+    }
+}
@@ -0,0 +1,510 @@
+// Statements. As in JavaScript, a missing semicolon is fine before a line
+// break, a '}' or the end of input.
+
+use lex::token::Token;
+use crate::ast::*;
+use super::{ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    // Statements up to the end of input. Errors are recovered from, see
+    // errors().
+    pub fn module(&mut self) -> Module {
+        let begin = self.peek_span().begin();
+        let mut body = Vec::new();
+        while self.peek() != Token::Eos {
+            let first = body.is_empty();
+            let statement = self.recovering_statement(|parser| match parser.peek() {
+                Token::Package | Token::Import | Token::Export => parser.module_declaration(first),
+                _ => parser.statement(),
+            });
+            body.push(statement);
+        }
+        Module { id: self.ids.fresh(), span: self.span_from(begin), body }
+    }
+
+    fn recovering_statement(&mut self, parse: impl FnOnce(&mut Parser<'a>) -> Result<Stmt, ParseError>) -> Stmt {
+        self.recovering(parse, |parser, begin| parser.stmt(begin, StmtKind::Error))
+    }
+
+    pub fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let begin = self.peek_span().begin();
+        let kind = match self.peek() {
+            Token::Lbrace => StmtKind::Block(self.block()?),
+            Token::Semicolon => {
+                self.bump();
+                StmtKind::Empty
+            },
+            Token::If => {
+                self.bump();
+                let condition = self.condition()?;
+                let then = Box::new(self.statement()?);
+                let otherwise = match self.eat(&Token::Else) {
+                    true => Some(Box::new(self.statement()?)),
+                    false => None,
+                };
+                StmtKind::If { condition, then, otherwise }
+            },
+            Token::While => {
+                self.bump();
+                let condition = self.condition()?;
+                StmtKind::While { condition, body: Box::new(self.statement()?) }
+            },
+            Token::Do => {
+                self.bump();
+                let body = Box::new(self.statement()?);
+                self.expect(Token::While)?;
+                let condition = self.condition()?;
+                // The semicolon after do-while is always optional.
+                self.eat(&Token::Semicolon);
+                StmtKind::DoWhile { body, condition }
+            },
+            Token::For => self.for_statement()?,
+            Token::Switch => self.switch_statement()?,
+            Token::Break | Token::Continue => {
+                let token = self.bump();
+                let label = match self.peek() {
+                    Token::Identifier(_) if !self.newline => Some(self.binding_identifier()?),
+                    _ => None,
+                };
+                self.semicolon()?;
+                match token {
+                    Token::Break => StmtKind::Break(label),
+                    _ => StmtKind::Continue(label),
+                }
+            },
+            Token::Return => {
+                self.bump();
+                let value = match self.at_statement_end() {
+                    true => None,
+                    false => Some(self.expression()?),
+                };
+                self.semicolon()?;
+                StmtKind::Return(value)
+            },
+            Token::Throw => {
+                self.bump();
+                if self.newline {
+                    return Err(self.error(self.prev, "line break after `throw`".to_string()));
+                }
+                let value = self.expression()?;
+                self.semicolon()?;
+                StmtKind::Throw(value)
+            },
+            Token::Try => self.try_statement()?,
+            Token::With => {
+                self.bump();
+                let object = self.condition()?;
+                StmtKind::With { object, body: Box::new(self.statement()?) }
+            },
+            Token::Package | Token::Import | Token::Export => {
+                let token = self.peek();
+                let message = format!("`{}` is only allowed at the top level of a module", token.value());
+                return Err(self.error(self.peek_span(), message));
+            },
+            Token::At
+            | Token::Public
+            | Token::Protected
+            | Token::Private
+            | Token::Static
+            | Token::Abstract
+            | Token::Native
+            | Token::Final
+            | Token::Function
+            | Token::Class
+            | Token::Interface
+            | Token::Enum => {
+                let decl = self.declaration()?;
+                if let DeclKind::Var(_) = decl.kind {
+                    self.semicolon()?;
+                }
+                StmtKind::Decl(decl)
+            },
+            _ if self.at_var_declaration() => {
+                let decl = self.var_declaration()?;
+                let decl = self.decl(begin, DeclKind::Var(decl));
+                self.semicolon()?;
+                StmtKind::Decl(decl)
+            },
+            _ => {
+                let expr = self.expression()?;
+                match expr.kind {
+                    ExprKind::Identifier(label) if self.eat(&Token::Colon) => {
+                        StmtKind::Labelled { label, body: Box::new(self.statement()?) }
+                    },
+                    _ => {
+                        self.semicolon()?;
+                        StmtKind::Expr(expr)
+                    },
+                }
+            },
+        };
+        Ok(self.stmt(begin, kind))
+    }
+
+    pub fn block(&mut self) -> Result<Block, ParseError> {
+        let begin = self.peek_span().begin();
+        self.expect(Token::Lbrace)?;
+        // `in` is an operator again in an arrow function body within the
+        // head of a for statement.
+        let body = self.with_in(true, |parser| {
+            let mut body = Vec::new();
+            while !matches!(parser.peek(), Token::Rbrace | Token::Eos) {
+                body.push(parser.recovering_statement(|parser| parser.statement()));
+            }
+            body
+        });
+        // An unclosed block is closed at the end of input.
+        if let Err(error) = self.expect(Token::Rbrace) {
+            self.report(error);
+        }
+        Ok(Block { id: self.ids.fresh(), span: self.span_from(begin), body })
+    }
+
+    // `for (init; condition; update)` and `for (target in object)`.
+    fn for_statement(&mut self) -> Result<StmtKind, ParseError> {
+        self.bump();
+        self.expect(Token::Lparen)?;
+        let init = match self.peek() {
+            Token::Semicolon => None,
+            _ if self.at_var_declaration() => Some(ForInit::Var(self.with_in(false, |parser| parser.var_declaration())?)),
+            _ => Some(ForInit::Expr(self.with_in(false, |parser| parser.expression())?)),
+        };
+        if let (Some(target), Token::In) = (&init, self.peek()) {
+            if let ForInit::Var(decl) = target {
+                if decl.declarators.len() != 1 || decl.declarators[0].init.is_some() {
+                    return Err(self.error(
+                        decl.span,
+                        "for-in declaration must be a single variable without initializer".to_string(),
+                    ));
+                }
+            }
+            let target = match init.unwrap() {
+                ForInit::Expr(target) => ForInit::Pattern(self.assignment_target(target)?),
+                target => target,
+            };
+            self.bump();
+            let object = self.expression()?;
+            self.expect(Token::Rparen)?;
+            let body = Box::new(self.statement()?);
+            return Ok(StmtKind::ForIn { target, object, body });
+        }
+        self.expect(Token::Semicolon)?;
+        let condition = match self.peek() {
+            Token::Semicolon => None,
+            _ => Some(self.expression()?),
+        };
+        self.expect(Token::Semicolon)?;
+        let update = match self.peek() {
+            Token::Rparen => None,
+            _ => Some(self.expression()?),
+        };
+        self.expect(Token::Rparen)?;
+        let body = Box::new(self.statement()?);
+        Ok(StmtKind::For { init, condition, update, body })
+    }
+
+    fn switch_statement(&mut self) -> Result<StmtKind, ParseError> {
+        self.bump();
+        let discriminant = self.condition()?;
+        self.expect(Token::Lbrace)?;
+        let mut cases = Vec::new();
+        let mut has_default = false;
+        while !self.eat(&Token::Rbrace) {
+            let begin = self.peek_span().begin();
+            let test = match self.bump() {
+                Token::Case => Some(self.expression()?),
+                Token::Default if has_default => {
+                    return Err(self.error(self.prev, "more than one default clause in switch".to_string()));
+                },
+                Token::Default => {
+                    has_default = true;
+                    None
+                },
+                token => return Err(self.unexpected(token, self.prev)),
+            };
+            self.expect(Token::Colon)?;
+            let mut body = Vec::new();
+            while !matches!(self.peek(), Token::Case | Token::Default | Token::Rbrace | Token::Eos) {
+                body.push(self.recovering_statement(|parser| parser.statement()));
+            }
+            cases.push(SwitchCase { id: self.ids.fresh(), span: self.span_from(begin), test, body });
+        }
+        Ok(StmtKind::Switch { discriminant, cases })
+    }
+
+    fn try_statement(&mut self) -> Result<StmtKind, ParseError> {
+        self.bump();
+        let block = self.block()?;
+        let catch = match self.peek() {
+            Token::Catch => {
+                let begin = self.peek_span().begin();
+                self.bump();
+                let param = match self.eat(&Token::Lparen) {
+                    true => {
+                        let param = self.binding_identifier()?;
+                        self.expect(Token::Rparen)?;
+                        Some(param)
+                    },
+                    false => None,
+                };
+                let body = self.block()?;
+                Some(CatchClause { id: self.ids.fresh(), span: self.span_from(begin), param, body })
+            },
+            _ => None,
+        };
+        let finally = match self.eat(&Token::Finally) {
+            true => Some(self.block()?),
+            false => None,
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peek_span(), "expected `catch` or `finally` after try block".to_string()));
+        }
+        Ok(StmtKind::Try { block, catch, finally })
+    }
+
+    // A parenthesized expression, as after `if` and `while`.
+    fn condition(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::Lparen)?;
+        let condition = self.with_in(true, |parser| parser.expression())?;
+        self.expect(Token::Rparen)?;
+        Ok(condition)
+    }
+
+    fn at_statement_end(&self) -> bool {
+        self.newline || matches!(self.peek(), Token::Semicolon | Token::Rbrace | Token::Eos)
+    }
+
+    // A missing semicolon is recorded and the statement taken as ended.
+    pub(super) fn semicolon(&mut self) -> Result<(), ParseError> {
+        if self.eat(&Token::Semicolon) || self.at_statement_end() {
+            return Ok(());
+        }
+        if let Err(error) = self.expect(Token::Semicolon) {
+            self.report(error);
+        }
+        Ok(())
+    }
+
+    pub(super) fn stmt(&mut self, begin: u32, kind: StmtKind) -> Stmt {
+        Stmt { id: self.ids.fresh(), span: self.span_from(begin), kind }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use lex::diagnostic::{self, Applicability, Diagnostic};
+    use lex::scanner::Span;
+    use lex::source::SourceMap;
+    use crate::ast::*;
+    use crate::parser::{parse_module, parse_module_recovering};
+    use crate::parser::sexp::{self, error, parse};
+
+    #[test]
+    fn parses_conditionals_and_loops() {
+        assert_eq!(parse("if (a) b; else if (c) { d } else e"), "(if a b (if c {d} e))");
+        assert_eq!(parse("while (i < n) i++"), "(while (< i n) (i++))");
+        assert_eq!(parse("do x(); while (y) z"), "(do (call x) y) z");
+        assert_eq!(parse("with (o) { f() }"), "(with o {(call f)})");
+        assert_eq!(parse("{} ; {;}"), "{} ; {;}");
+    }
+
+    #[test]
+    fn parses_for_statements() {
+        assert_eq!(parse("for (i = 0; i < n; i++) ;"), "(for (= i 0) (< i n) (i++) ;)");
+        assert_eq!(parse("for (;;) {}"), "(for _ _ _ {})");
+        assert_eq!(parse("for (k in o) f(k)"), "(for-in k o (call f k))");
+        assert_eq!(parse("for (a.b in c in d) ;"), "(for-in (. a b) (in c d) ;)");
+        assert_eq!(parse("for (x = (a in b); x;) ;"), "(for (= x (paren (in a b))) x _ ;)");
+        assert_eq!(parse("for (f = x => { a in b; }; f;) ;"), "(for (= f (=> (x) {(in a b)})) f _ ;)");
+        assert_eq!(error("for (1 in o) ;"), (5, 6, "invalid assignment target".to_string()));
+        assert_eq!(error("for (a; b) ;"), (9, 10, "expected `;`, found `)`".to_string()));
+    }
+
+    #[test]
+    fn parses_jumps_and_labels() {
+        assert_eq!(parse("outer: for (;;) { break outer; continue }"), "(outer: (for _ _ _ {(break outer) (continue)}))");
+        assert_eq!(parse("return\na"), "(return) a");
+        assert_eq!(parse("l: while (x) break\nl"), "(l: (while x (break))) l");
+        assert_eq!(parse("{ return a + b }"), "{(return (+ a b))}");
+        assert_eq!(parse("throw new E('x');"), "(throw (new E ('x')))");
+        assert_eq!(error("throw\nx"), (0, 5, "line break after `throw`".to_string()));
+        assert_eq!(error("a + b c"), (6, 7, "expected `;`, found identifier `c`".to_string()));
+    }
+
+    #[test]
+    fn parses_switch() {
+        assert_eq!(
+            parse("switch (x) { case 1: case 2: a(); break; default: b }"),
+            "(switch x (case 1) (case 2 (call a) (break)) (default b))"
+        );
+        assert_eq!(error("switch (x) { default: default: }"), (22, 29, "more than one default clause in switch".to_string()));
+        assert_eq!(error("switch (x) { a }"), (13, 14, "unexpected identifier `a`".to_string()));
+    }
+
+    #[test]
+    fn parses_try() {
+        assert_eq!(parse("try { a } catch (e) { b } finally { c }"), "(try {a} (catch e {b}) (finally {c}))");
+        assert_eq!(parse("try {} catch {}"), "(try {} (catch {}))");
+        assert_eq!(parse("try {} finally {}"), "(try {} (finally {}))");
+        assert_eq!(error("try {} x"), (7, 8, "expected `catch` or `finally` after try block".to_string()));
+    }
+
+    #[test]
+    fn records_spans() {
+        let module = parse_module("  if (a) {\n  b;\n} else c\n").unwrap();
+        assert_eq!(module.span, Span::new(2, 24));
+        let statement = &module.body[0];
+        assert_eq!(statement.span, Span::new(2, 24));
+        match &statement.kind {
+            StmtKind::If { then, otherwise: Some(otherwise), .. } => {
+                assert_eq!(then.span, Span::new(9, 17));
+                assert_eq!(otherwise.span, Span::new(23, 24));
+                match &then.kind {
+                    StmtKind::Block(block) => assert_eq!(block.body[0].span, Span::new(13, 15)),
+                    kind => panic!("unexpected {:?}", kind),
+                }
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+
+    fn recover(code: &str) -> (String, Vec<(u32, u32, String)>) {
+        let (module, errors) = parse_module_recovering(code);
+        let statements: Vec<String> = module.body.iter().map(sexp::stmt).collect();
+        let errors = errors
+            .into_iter()
+            .map(|error| (error.location.begin(), error.location.end(), error.message))
+            .collect();
+        (statements.join(" "), errors)
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        assert_eq!(
+            recover("x = ;\ny = 2;"),
+            ("(= x (error)) (= y 2)".to_string(), vec![(4, 5, "unexpected `;`".to_string())])
+        );
+        assert_eq!(
+            recover("a = 1 b = 2\nc()"),
+            ("(= a 1) (= b 2) (call c)".to_string(), vec![(6, 7, "expected `;`, found identifier `b`".to_string())])
+        );
+        assert_eq!(
+            recover("def f() { var = 1;\n return 2 }\ndef g() {}"),
+            (
+                "(def f () {(error) (return 2)}) (def g () {})".to_string(),
+                vec![(14, 15, "expected identifier, found `=`".to_string())]
+            )
+        );
+        assert_eq!(
+            recover("var o = {a 1};\nnext()"),
+            ("(error) (call next)".to_string(), vec![(11, 12, "expected `:`, found number `1`".to_string())])
+        );
+        assert_eq!(
+            recover("x = if (a) b"),
+            ("(error) (if a b)".to_string(), vec![(4, 6, "unexpected `if`".to_string())])
+        );
+        assert_eq!(
+            recover("}\n) x"),
+            (
+                "(error) (error) x".to_string(),
+                vec![(0, 1, "unexpected `}`".to_string()), (2, 3, "unexpected `)`".to_string())]
+            )
+        );
+        assert_eq!(
+            recover("{ a; { b"),
+            ("{a {b}}".to_string(), vec![(8, 8, "expected `}`, found end of input".to_string())])
+        );
+    }
+
+    #[test]
+    fn reports_every_error_once() {
+        let (tree, errors) = recover(
+            "class A {\n int x = ;\n def m() { 1 + }\n bad bad bad;\n def n() {}\n}\nif (a +) { b( } c;",
+        );
+        assert_eq!(
+            tree,
+            "(class A {(field int x (error)) (method m (def () {(+ 1 (error))})) (error) (method n (def () {}))}) \
+             (if (+ a (error)) {(error)}) c"
+        );
+        assert_eq!(
+            errors,
+            vec![
+                (19, 20, "unexpected `;`".to_string()),
+                (36, 37, "unexpected `}`".to_string()),
+                (47, 50, "expected `;`, found identifier `bad`".to_string()),
+                (73, 74, "unexpected `)`".to_string()),
+                (80, 81, "unexpected `}`".to_string()),
+            ]
+        );
+        assert_eq!(parse_module("x = ; y +").unwrap_err().message, "unexpected `;`");
+    }
+
+    #[test]
+    fn records_error_node_spans() {
+        let (module, _) = parse_module_recovering("f(a,, b);\nvar = 1;\ng()");
+        assert_eq!(module.body[1].kind, StmtKind::Error);
+        assert_eq!(module.body[1].span, Span::new(10, 18));
+        match &module.body[0].kind {
+            StmtKind::Expr(Expr { kind: ExprKind::Call { arguments, .. }, .. }) => {
+                assert_eq!(arguments[1].kind, ExprKind::Error);
+                assert_eq!(arguments[1].span, Span::new(4, 4));
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+        assert_eq!(module.body.len(), 3);
+    }
+
+    #[test]
+    fn suggests_fixes() {
+        let code = "x = (a;\nif (a {}\nb = 1 c = 2";
+        let (_, errors) = parse_module_recovering(code);
+        let fixes: Vec<(String, Applicability)> = errors
+            .iter()
+            .map(|error| {
+                let suggestion = error.suggestion.as_ref().unwrap();
+                (suggestion.apply(code), suggestion.applicability)
+            })
+            .collect();
+        assert_eq!(
+            fixes,
+            vec![
+                ("x = (a);\nif (a {}\nb = 1 c = 2".to_string(), Applicability::MaybeIncorrect),
+                ("x = (a;\nif (a) {}\nb = 1 c = 2".to_string(), Applicability::MaybeIncorrect),
+                ("x = (a;\nif (a {}\nb = 1; c = 2".to_string(), Applicability::MaybeIncorrect),
+            ]
+        );
+
+        let map = SourceMap::new("main.mt", code);
+        let diagnostic = Diagnostic::from(errors[2].clone());
+        assert_eq!(
+            diagnostic::render(&diagnostic, &map, false),
+            "error: expected `;`, found identifier `c`\n\
+             \x20--> main.mt:3:7\n\
+             \x20 |\n\
+             3 | b = 1 c = 2\n\
+             \x20 |       ^\n\
+             help: insert `;`\n\
+             \x20 |\n\
+             3 | b = 1; c = 2\n\
+             \x20 |      +\n"
+        );
+    }
+
+    #[test]
+    fn applies_certain_fixes_cleanly() {
+        let fix = |code: &str| {
+            let (_, errors) = parse_module_recovering(code);
+            let suggestion = errors[0].suggestion.clone().unwrap();
+            let (_, remaining) = parse_module_recovering(&suggestion.apply(code));
+            (suggestion.applicability, remaining.is_empty())
+        };
+        for code in ["a = 1 if (a) b()", "x = f() var y = 2", "while (a) x = 1 return"] {
+            assert_eq!(fix(code), (Applicability::MachineApplicable, true), "{}", code);
+        }
+        // Inserting `;` would not fix these.
+        assert_eq!(fix("x = 1 )"), (Applicability::MaybeIncorrect, false));
+        assert_eq!(fix("for (a b) ;"), (Applicability::MaybeIncorrect, false));
+    }
+}
@@ -0,0 +1,145 @@
+// Types: primitives, dotted names, generic applications `List<int>`, array
+// types `T[]`, nullable types `T?` and function types `(A, B) => R`.
+
+use lex::token::Token;
+use crate::ast::*;
+use super::{describe, ParseError, Parser};
+
+impl<'a> Parser<'a> {
+
+    pub fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let begin = self.peek_span().begin();
+        let kind = match self.peek() {
+            Token::Lparen => {
+                self.bump();
+                let params = self.list(Token::Rparen, |parser| parser.parse_type())?;
+                self.expect_arrow()?;
+                let result = Box::new(self.parse_type()?);
+                TypeKind::Function { params, result }
+            },
+            Token::Identifier(_) => {
+                let base = self.path()?;
+                match self.eat(&Token::Lt) {
+                    true => TypeKind::Generic { base, arguments: self.type_arguments()? },
+                    false => TypeKind::Named(base),
+                }
+            },
+            token => match Primitive::from_token(&token) {
+                Some(primitive) => {
+                    self.bump();
+                    TypeKind::Primitive(primitive)
+                },
+                None => return Err(self.error(self.peek_span(), format!("expected type, found {}", describe(&token)))),
+            },
+        };
+        let mut ty = self.ty(begin, kind);
+        loop {
+            let kind = match self.peek() {
+                Token::Lbrack => {
+                    self.bump();
+                    self.expect(Token::Rbrack)?;
+                    TypeKind::Array(Box::new(ty))
+                },
+                Token::Conditional => {
+                    self.bump();
+                    TypeKind::Nullable(Box::new(ty))
+                },
+                _ => return Ok(ty),
+            };
+            ty = self.ty(begin, kind);
+        }
+    }
+
+    // `a.b.C`
+    pub(super) fn path(&mut self) -> Result<Path, ParseError> {
+        let begin = self.peek_span().begin();
+        let mut segments = vec![self.binding_identifier()?];
+        while self.eat(&Token::Period) {
+            segments.push(self.binding_identifier()?);
+        }
+        Ok(Path { id: self.ids.fresh(), span: self.span_from(begin), segments })
+    }
+
+    // The arguments after the '<' of a generic type, including the closing
+    // '>'. Nested generics close with `>>` or `>>>`, which the scanner
+    // returns as one shift token; those are split so that each level
+    // consumes a single '>'.
+    fn type_arguments(&mut self) -> Result<Vec<Type>, ParseError> {
+        let mut arguments = vec![self.parse_type()?];
+        while self.eat(&Token::Comma) {
+            arguments.push(self.parse_type()?);
+        }
+        match self.peek() {
+            Token::Gt => self.bump(),
+            Token::Sar => self.split(1, Token::Gt, Token::Gt),
+            Token::Shr => self.split(1, Token::Gt, Token::Sar),
+            Token::Gte => self.split(1, Token::Gt, Token::Assign),
+            Token::AssignSar => self.split(1, Token::Gt, Token::Gte),
+            Token::AssignShr => self.split(1, Token::Gt, Token::AssignSar),
+            _ => {
+                self.expect(Token::Gt)?;
+                Token::Gt
+            },
+        };
+        Ok(arguments)
+    }
+
+    fn ty(&mut self, begin: u32, kind: TypeKind) -> Type {
+        Type { id: self.ids.fresh(), span: self.span_from(begin), kind }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use lex::scanner::{Scanner, Span};
+    use lex::token::Token;
+    use crate::ast::*;
+    use crate::parser::{sexp, ParseError, Parser};
+
+    fn parse(code: &str) -> Result<Type, ParseError> {
+        let mut parser = Parser::new(Scanner::new(code, 0));
+        let ty = parser.parse_type()?;
+        parser.expect_end()?;
+        Ok(ty)
+    }
+
+    fn show(code: &str) -> String {
+        sexp::ty(&parse(code).unwrap())
+    }
+
+    #[test]
+    fn parses_types() {
+        assert_eq!(show("int"), "int");
+        assert_eq!(show("bool[][]"), "bool[][]");
+        assert_eq!(show("a.b.Name?"), "a.b.Name?");
+        assert_eq!(show("Map<string, int[]>?"), "Map<string, int[]>?");
+        assert_eq!(show("(int, List<T>) => void"), "(int, List<T>) => void");
+        assert_eq!(show("() => (int) => int"), "() => (int) => int");
+        assert_eq!(parse("(int) = > int").unwrap_err().message, "expected `=>`, found `=`");
+        assert_eq!(parse("if").unwrap_err().message, "expected type, found `if`");
+    }
+
+    #[test]
+    fn splits_shift_tokens_closing_generics() {
+        assert_eq!(show("List<List<int>>"), "List<List<int>>");
+        assert_eq!(show("A<B<C<int>>>"), "A<B<C<int>>>");
+        assert_eq!(show("A<B<C<D<int>>>>"), "A<B<C<D<int>>>>");
+        let ty = parse("List<Map<K, V>>[]").unwrap();
+        assert_eq!(ty.span, Span::new(0, 17));
+        match &ty.kind {
+            TypeKind::Array(element) => match &element.kind {
+                TypeKind::Generic { arguments, .. } => assert_eq!(arguments[0].span, Span::new(5, 14)),
+                kind => panic!("unexpected {:?}", kind),
+            },
+            kind => panic!("unexpected {:?}", kind),
+        }
+
+        // What is left of a split token is still there for the caller.
+        let mut parser = Parser::new(Scanner::new("List<int>>= x", 0));
+        assert_eq!(sexp::ty(&parser.parse_type().unwrap()), "List<int>");
+        assert_eq!(parser.peek(), Token::Gte);
+        assert_eq!(parser.peek_span(), Span::new(9, 11));
+        assert!(parse("List<int>>").is_err());
+    }
+}